@@ -0,0 +1,97 @@
+use std::sync::Arc;
+use std::time::{ Duration, Instant };
+
+use crate::{ AsyncMsgQueue, MsgQueueError, MsgQueueError::* };
+
+/// The results of a `run_throughput` run
+#[derive(Debug)]
+pub struct ThroughputReport {
+    pub messages: usize,
+    pub elapsed: Duration,
+    pub messages_per_sec: f64,
+    /// Median time between a message being sent and read
+    pub p50_latency: Duration,
+    /// 99th percentile time between a message being sent and read
+    pub p99_latency: Duration,
+}
+
+/// Sends `n_messages` through a queue built by `config`, split between `n_writers`
+/// writer threads and read by `n_readers` reader threads
+/// 
+/// Each message is the `Instant` it was sent, so readers can measure its latency.
+/// Writers retry when a bounded queue is full.
+/// 
+/// Returns `NegativeWriters` if `n_writers` is 0, as the queue would never close for the readers to finish,
+/// and `NegativeReaders` if `n_readers` is 0, as nothing would be read. A panic in a writer or reader
+/// thread is resumed on the calling thread.
+pub fn run_throughput<F: FnOnce() -> AsyncMsgQueue<Instant>>(
+    config: F,
+    n_writers: usize,
+    n_readers: usize,
+    n_messages: usize,
+) -> Result<ThroughputReport, MsgQueueError> {
+    if n_writers == 0 { return Err(NegativeWriters) }
+    if n_readers == 0 { return Err(NegativeReaders) }
+
+    let queue = Arc::new(config());
+
+    let writer_ids = (0..n_writers)
+        .map(|_| queue.register_writer())
+        .collect::<Result<Vec<_>, _>>()?;
+
+    let start = Instant::now();
+
+    let reader_handles: Vec<_> = (0..n_readers).map(|_| {
+        let reader = queue.clone();
+
+        std::thread::spawn(move || reader.fold(vec![], |mut latencies, sent: Instant| {
+            latencies.push(sent.elapsed());
+            latencies
+        }))
+    }).collect();
+
+    let writer_handles: Vec<_> = writer_ids.into_iter().enumerate().map(|(i, id)| {
+        let writer = queue.clone();
+        let count = n_messages / n_writers + usize::from(i < n_messages % n_writers);
+
+        std::thread::spawn(move || {
+            for _ in 0..count {
+                loop {
+                    match writer.send(id, Instant::now()) {
+                        Err(QueueFull) => std::thread::yield_now(),
+                        result => break result?,
+                    }
+                }
+            }
+
+            writer.deregister_writer(id)
+        })
+    }).collect();
+
+    for handle in writer_handles {
+        handle.join().unwrap_or_else(|panic| std::panic::resume_unwind(panic))?;
+    }
+
+    let mut latencies = vec![];
+
+    for handle in reader_handles {
+        latencies.extend(handle.join().unwrap_or_else(|panic| std::panic::resume_unwind(panic))?);
+    }
+
+    let elapsed = start.elapsed();
+
+    latencies.sort();
+
+    let percentile = |p: usize| latencies
+        .get((latencies.len() * p / 100).min(latencies.len().saturating_sub(1)))
+        .copied()
+        .unwrap_or_default();
+
+    Ok(ThroughputReport {
+        messages: latencies.len(),
+        elapsed,
+        messages_per_sec: latencies.len() as f64 / elapsed.as_secs_f64(),
+        p50_latency: percentile(50),
+        p99_latency: percentile(99),
+    })
+}
@@ -0,0 +1,61 @@
+use std::sync::{ Arc, Mutex };
+use std::sync::atomic::{ AtomicBool, AtomicUsize, Ordering::SeqCst };
+
+type Waker = Box<dyn Fn() + Send + Sync>;
+
+#[derive(Default)]
+struct CancelState {
+    cancelled: AtomicBool,
+    next_waker_id: AtomicUsize,
+    wakers: Mutex<Vec<(usize, Waker)>>,
+}
+
+/// Cancels reads made with `AsyncMsgQueue::read_cancellable`
+/// 
+/// Clones share the same cancellation state, so a clone can be handed to
+/// another thread to cancel the reader.
+#[derive(Clone, Default)]
+pub struct CancelToken {
+    state: Arc<CancelState>,
+}
+
+impl CancelToken {
+    pub fn new() -> Self { Self::default() }
+
+    /// Cancels every read using this token, waking any that are waiting
+    pub fn cancel(&self) {
+        self.state.cancelled.store(true, SeqCst);
+
+        if let Ok(wakers) = self.state.wakers.lock() {
+            for (_, wake) in wakers.iter() { wake() }
+        }
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        self.state.cancelled.load(SeqCst)
+    }
+
+    /// Calls `wake` when the token is cancelled, until the returned guard is dropped
+    pub(crate) fn on_cancel<F: Fn() + Send + Sync + 'static>(&self, wake: F) -> WakerGuard<'_> {
+        let id = self.state.next_waker_id.fetch_add(1, SeqCst);
+
+        if let Ok(mut wakers) = self.state.wakers.lock() {
+            wakers.push((id, Box::new(wake)));
+        }
+
+        WakerGuard { token: self, id }
+    }
+}
+
+pub(crate) struct WakerGuard<'a> {
+    token: &'a CancelToken,
+    id: usize,
+}
+
+impl Drop for WakerGuard<'_> {
+    fn drop(&mut self) {
+        if let Ok(mut wakers) = self.token.state.wakers.lock() {
+            wakers.retain(|(id, _)| *id != self.id);
+        }
+    }
+}
@@ -0,0 +1,74 @@
+use std::sync::Arc;
+
+use crate::{ AsyncMsgQueue, MsgQueueError, WriterGuard };
+
+/// Creates a queue split into a writing and a reading half
+/// 
+/// Each `Writer` is a registered writer, so the queue closes once every `Writer` has been dropped.
+/// Registering can fail, so writers are copied with `try_clone` rather than `Clone`.
+/// Cloning the `Reader` shares the queue's messages between the readers.
+/// 
+/// ```
+/// use async_msg_queue::{ channel, MsgQueueError::* };
+/// 
+/// let (writer, reader) = channel().unwrap();
+/// 
+/// let other_writer = writer.try_clone().unwrap();
+/// std::thread::spawn(move || other_writer.send(1)).join().unwrap().unwrap();
+/// 
+/// assert_eq!(writer.send(2), Ok(()));
+/// drop(writer);
+/// 
+/// assert_eq!(reader.read(), Ok(1));
+/// assert_eq!(reader.read(), Ok(2));
+/// assert_eq!(reader.read(), Err(EndOfTransmission));
+/// ```
+pub fn channel<T: 'static>() -> Result<(Writer<T>, Reader<T>), MsgQueueError> {
+    let queue = AsyncMsgQueue::new_arc();
+
+    Ok((Writer::new(queue.clone())?, Reader { queue }))
+}
+
+/// The writing half of a `channel`, a `WriterGuard` that deregisters when dropped
+pub struct Writer<T: 'static> {
+    queue: Arc<AsyncMsgQueue<T>>,
+    guard: WriterGuard<'static, T>,
+}
+
+impl<T: 'static> Writer<T> {
+    fn new(queue: Arc<AsyncMsgQueue<T>>) -> Result<Self, MsgQueueError> {
+        let guard = queue.register_writer_guard_arc()?;
+        Ok(Self { queue, guard })
+    }
+
+    pub fn send(&self, t: T) -> Result<(), MsgQueueError> {
+        self.guard.send(t)
+    }
+
+    /// Registers another writer on the same queue
+    pub fn try_clone(&self) -> Result<Self, MsgQueueError> {
+        Self::new(self.queue.clone())
+    }
+}
+
+/// The reading half of a `channel`
+pub struct Reader<T> {
+    queue: Arc<AsyncMsgQueue<T>>,
+}
+
+impl<T> Reader<T> {
+    /// Waits for the next message, see `AsyncMsgQueue::read`
+    pub fn read(&self) -> Result<T, MsgQueueError> {
+        self.queue.read()
+    }
+
+    /// Reads the next message without waiting, see `AsyncMsgQueue::try_read`
+    pub fn try_read(&self) -> Result<T, MsgQueueError> {
+        self.queue.try_read()
+    }
+}
+
+/// Clones share the queue, so each message is read by only one of them
+impl<T> Clone for Reader<T> {
+    fn clone(&self) -> Self { Self { queue: self.queue.clone() } }
+}
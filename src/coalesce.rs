@@ -0,0 +1,84 @@
+use std::sync::Arc;
+use std::time::{ Duration, Instant };
+
+use crate::{ AsyncMsgQueue, MsgQueueError, WriterID };
+
+struct Entry<K, T> {
+    key: K,
+    /// Only `None` while a new value is being merged in, under the queue lock
+    value: Option<T>,
+    /// When the first message merged into this entry was sent
+    sent: Instant,
+}
+
+/// A message queue that merges messages with the same key sent within a time window
+/// 
+/// Sending a message with the same key as an unread message sent less than `window` ago
+/// merges the two in place, so the merged message keeps the position of the first.
+/// Once the window has passed, a message with that key starts a new entry.
+pub struct CoalesceQueue<K, T> {
+    inner: AsyncMsgQueue<Entry<K, T>>,
+    window: Duration,
+}
+
+/// ```
+/// use async_msg_queue::CoalesceQueue;
+/// use std::time::Duration;
+/// 
+/// let queue = CoalesceQueue::<&str, u32>::new(Duration::from_secs(60));
+/// 
+/// let writer_handle = queue.register_writer().unwrap();
+/// 
+/// assert_eq!(queue.send_coalesced(writer_handle, "hits", 1, |a, b| a + b), Ok(()));
+/// assert_eq!(queue.send_coalesced(writer_handle, "misses", 1, |a, b| a + b), Ok(()));
+/// assert_eq!(queue.send_coalesced(writer_handle, "hits", 2, |a, b| a + b), Ok(()));
+/// 
+/// assert_eq!(queue.read(), Ok(("hits", 3)));
+/// assert_eq!(queue.read(), Ok(("misses", 1)));
+/// ```
+impl<K, T> CoalesceQueue<K, T> {
+    pub fn new(window: Duration) -> Self {
+        Self { inner: AsyncMsgQueue::new(), window }
+    }
+
+    pub fn new_arc(window: Duration) -> Arc<Self> { Arc::new(Self::new(window)) }
+
+    pub fn register_writer(&self) -> Result<WriterID, MsgQueueError> {
+        self.inner.register_writer()
+    }
+
+    pub fn deregister_writer(&self, id: WriterID) -> Result<(), MsgQueueError> {
+        self.inner.deregister_writer(id)
+    }
+
+    pub fn is_closed(&self) -> Result<bool, MsgQueueError> {
+        self.inner.is_closed()
+    }
+
+    pub fn is_terminated(&self) -> Result<bool, MsgQueueError> {
+        self.inner.is_terminated()
+    }
+
+    /// Enqueues a message, merging it into an unread message with the same key sent within the window
+    /// 
+    /// `merge` is given the buffered value followed by the new one
+    pub fn send_coalesced(&self, id: WriterID, key: K, t: T, merge: fn(T, T) -> T) -> Result<(), MsgQueueError>
+    where K: PartialEq {
+        let entry = Entry { key, value: Some(t), sent: Instant::now() };
+
+        self.inner.send_with(id, entry, |queue, entry| {
+            let buffered = queue.find_mut(|buffered| buffered.key == entry.key && buffered.sent.elapsed() < self.window);
+
+            match buffered {
+                Some(buffered) => buffered.value = buffered.value.take().zip(entry.value).map(|(value, t)| merge(value, t)),
+                None => queue.push(entry),
+            }
+        })
+    }
+
+    /// Reads the next message and its key from the queue
+    pub fn read(&self) -> Result<(K, T), MsgQueueError> {
+        self.inner.read()
+            .map(|entry| (entry.key, entry.value.expect("values are only taken while merging")))
+    }
+}
@@ -0,0 +1,3 @@
+//! Adaptors that mirror other channel APIs, to ease migrating to `AsyncMsgQueue`
+
+pub mod mpsc;
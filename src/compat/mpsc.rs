@@ -0,0 +1,232 @@
+//! A drop-in replacement for `std::sync::mpsc`, built on `AsyncMsgQueue`
+//! 
+//! Senders register as writers when created or cloned and deregister when dropped,
+//! so the receiver sees the channel disconnect once every sender is gone.
+//! Dropping the receiver terminates the queue, so later sends fail.
+
+use std::fmt;
+use std::sync::Arc;
+use std::time::{ Duration, Instant };
+
+use crate::{ AsyncMsgQueue, MsgQueueError, MsgQueueError::*, OverflowPolicy, WriterID };
+
+/// Returned by `send` when the receiver has been dropped, along with the unsent message
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct SendError<T>(pub T);
+
+/// Returned by `try_send` when the message couldn't be sent, along with the unsent message
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum TrySendError<T> {
+    Full(T),
+    Disconnected(T),
+}
+
+/// Returned by `recv` when every sender has been dropped and the channel is empty
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct RecvError;
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum TryRecvError {
+    Empty,
+    Disconnected,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum RecvTimeoutError {
+    Timeout,
+    Disconnected,
+}
+
+impl<T> fmt::Display for SendError<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("sending on a closed channel")
+    }
+}
+
+impl<T> fmt::Display for TrySendError<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            TrySendError::Full(_) => "sending on a full channel",
+            TrySendError::Disconnected(_) => "sending on a closed channel",
+        })
+    }
+}
+
+impl fmt::Display for RecvError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("receiving on a closed channel")
+    }
+}
+
+impl fmt::Display for TryRecvError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            TryRecvError::Empty => "receiving on an empty channel",
+            TryRecvError::Disconnected => "receiving on a closed channel",
+        })
+    }
+}
+
+impl fmt::Display for RecvTimeoutError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            RecvTimeoutError::Timeout => "timed out waiting on channel",
+            RecvTimeoutError::Disconnected => "channel is empty and sending half is closed",
+        })
+    }
+}
+
+impl<T: fmt::Debug> std::error::Error for SendError<T> {}
+impl<T: fmt::Debug> std::error::Error for TrySendError<T> {}
+impl std::error::Error for RecvError {}
+impl std::error::Error for TryRecvError {}
+impl std::error::Error for RecvTimeoutError {}
+
+/// Every error other than an empty queue means the channel can no longer be used
+impl From<MsgQueueError> for TryRecvError {
+    fn from(e: MsgQueueError) -> Self {
+        match e {
+            NoMessages => TryRecvError::Empty,
+            _ => TryRecvError::Disconnected,
+        }
+    }
+}
+
+impl From<MsgQueueError> for RecvTimeoutError {
+    fn from(e: MsgQueueError) -> Self {
+        match e {
+            Timeout => RecvTimeoutError::Timeout,
+            _ => RecvTimeoutError::Disconnected,
+        }
+    }
+}
+
+/// Creates an unbounded channel
+pub fn channel<T>() -> (Sender<T>, Receiver<T>) {
+    let queue = AsyncMsgQueue::new_arc();
+
+    (Sender::new(queue.clone()), Receiver { queue })
+}
+
+/// Creates a channel that holds at most `bound` unread messages
+/// 
+/// Unlike `std::sync::mpsc::sync_channel`, a bound of 0 is treated as 1,
+/// since the queue has no rendezvous mode
+pub fn sync_channel<T>(bound: usize) -> (SyncSender<T>, Receiver<T>) {
+    let queue = Arc::new(AsyncMsgQueue::new().with_capacity_policy(bound.max(1), OverflowPolicy::Block));
+
+    (SyncSender { inner: Sender::new(queue.clone()) }, Receiver { queue })
+}
+
+pub struct Sender<T> {
+    queue: Arc<AsyncMsgQueue<T>>,
+    id: Option<WriterID>,
+}
+
+impl<T> Sender<T> {
+    fn new(queue: Arc<AsyncMsgQueue<T>>) -> Self {
+        let id = queue.register_writer().ok();
+        Self { queue, id }
+    }
+
+    pub fn send(&self, t: T) -> Result<(), SendError<T>> {
+        let Some(id) = self.id else { return Err(SendError(t)) };
+
+        self.queue.send_or_return(id, t, crate::SendWait::Never, crate::Queue::push)
+            .map(|_| ())
+            .map_err(|(_, t)| SendError(t))
+    }
+}
+
+impl<T> Clone for Sender<T> {
+    fn clone(&self) -> Self { Self::new(self.queue.clone()) }
+}
+
+impl<T> Drop for Sender<T> {
+    fn drop(&mut self) {
+        if let Some(id) = self.id { let _ = self.queue.deregister_writer(id); }
+    }
+}
+
+impl<T> fmt::Debug for Sender<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("Sender { .. }")
+    }
+}
+
+pub struct SyncSender<T> {
+    inner: Sender<T>,
+}
+
+impl<T> SyncSender<T> {
+    /// Sends a message, waiting for space if the channel is full
+    pub fn send(&self, t: T) -> Result<(), SendError<T>> {
+        let Some(id) = self.inner.id else { return Err(SendError(t)) };
+
+        self.inner.queue.send_or_return(id, t, crate::SendWait::Forever, crate::Queue::push)
+            .map(|_| ())
+            .map_err(|(_, t)| SendError(t))
+    }
+
+    pub fn try_send(&self, t: T) -> Result<(), TrySendError<T>> {
+        let Some(id) = self.inner.id else { return Err(TrySendError::Disconnected(t)) };
+
+        self.inner.queue.send_or_return(id, t, crate::SendWait::Never, crate::Queue::push)
+            .map(|_| ())
+            .map_err(|(e, t)| match e {
+                QueueFull => TrySendError::Full(t),
+                _ => TrySendError::Disconnected(t),
+            })
+    }
+}
+
+impl<T> Clone for SyncSender<T> {
+    fn clone(&self) -> Self { Self { inner: self.inner.clone() } }
+}
+
+impl<T> fmt::Debug for SyncSender<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("SyncSender { .. }")
+    }
+}
+
+pub struct Receiver<T> {
+    queue: Arc<AsyncMsgQueue<T>>,
+}
+
+impl<T> Receiver<T> {
+    /// Waits for a message, failing once every sender has been dropped and the channel is empty
+    pub fn recv(&self) -> Result<T, RecvError> {
+        self.queue.read().map_err(|_| RecvError)
+    }
+
+    pub fn try_recv(&self) -> Result<T, TryRecvError> {
+        Ok(self.queue.try_read()?)
+    }
+
+    pub fn recv_timeout(&self, timeout: Duration) -> Result<T, RecvTimeoutError> {
+        Ok(self.queue.read_deadline(Instant::now() + timeout)?)
+    }
+
+    /// Iterates over messages, waiting for each one, until the channel disconnects
+    pub fn iter(&self) -> impl Iterator<Item = T> + '_ {
+        std::iter::from_fn(|| self.recv().ok())
+    }
+
+    /// Iterates over the messages available now without waiting
+    pub fn try_iter(&self) -> impl Iterator<Item = T> + '_ {
+        std::iter::from_fn(|| self.try_recv().ok())
+    }
+}
+
+impl<T> Drop for Receiver<T> {
+    fn drop(&mut self) {
+        let _ = self.queue.force_terminate();
+    }
+}
+
+impl<T> fmt::Debug for Receiver<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("Receiver { .. }")
+    }
+}
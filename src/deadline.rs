@@ -0,0 +1,66 @@
+use std::sync::{ Arc, Condvar, Mutex };
+use std::time::Instant;
+
+use crate::{ lock, MsgQueueError, MsgQueueError::* };
+
+#[derive(Default)]
+struct State {
+    when: Option<Instant>,
+    /// Whether a thread is waiting for `when`, so rescheduling doesn't need to spawn another
+    timer_running: bool,
+}
+
+/// The deadline set by `AsyncMsgQueue::terminate_at`, shared with the one thread waiting for it
+#[derive(Default)]
+pub(crate) struct Deadline {
+    state: Mutex<State>,
+    changed: Condvar,
+}
+
+impl Deadline {
+    /// Sets or clears the deadline, waking the timer thread,
+    /// and returns whether there is no timer thread yet, so one must be spawned
+    pub(crate) fn set(&self, when: Option<Instant>) -> Result<bool, MsgQueueError> {
+        let mut state = lock(&self.state)?;
+
+        state.when = when;
+        self.changed.notify_all();
+
+        let spawn = when.is_some() && !state.timer_running;
+        state.timer_running |= spawn;
+
+        Ok(spawn)
+    }
+
+    /// Waits for the deadline to pass, following it as it is rescheduled,
+    /// and returns false if it is cleared first
+    pub(crate) fn wait(&self) -> Result<bool, MsgQueueError> {
+        let mut state = lock(&self.state)?;
+
+        loop {
+            let now = Instant::now();
+
+            match state.when {
+                Some(when) if now < when => {
+                    state = self.changed.wait_timeout(state, when - now).map_err(|_| NoLock)?.0;
+                },
+                when => {
+                    state.when = None;
+                    state.timer_running = false;
+                    return Ok(when.is_some())
+                },
+            }
+        }
+    }
+}
+
+/// The queue's handle to its deadline, which clears it when the queue is dropped,
+/// so the timer thread doesn't outlive the queue
+#[derive(Default)]
+pub(crate) struct DeadlineTimer(pub(crate) Arc<Deadline>);
+
+impl Drop for DeadlineTimer {
+    fn drop(&mut self) {
+        let _ = self.0.set(None);
+    }
+}
@@ -0,0 +1,92 @@
+use std::collections::HashSet;
+use std::hash::Hash;
+use std::sync::{ Arc, Mutex };
+
+use crate::{ AsyncMsgQueue, MsgQueueError, Queue, WriterID };
+
+/// A message queue that drops messages identical to one that's already buffered
+/// 
+/// Deduplication is only against messages currently in the queue: once a message
+/// has been read, an identical message can be sent again.
+/// The queue keeps a clone of every buffered message in a `HashSet`,
+/// so buffered messages take up roughly twice the memory they would in an `AsyncMsgQueue`.
+pub struct DedupQueue<T> {
+    inner: AsyncMsgQueue<T>,
+    buffered: Mutex<HashSet<T>>,
+}
+
+impl<T> Default for DedupQueue<T> {
+    fn default() -> Self { Self::new() }
+}
+
+/// ```
+/// use async_msg_queue::DedupQueue;
+/// 
+/// let queue = DedupQueue::<&str>::new();
+/// 
+/// let writer_handle = queue.register_writer().unwrap();
+/// 
+/// assert_eq!(queue.send(writer_handle, "a"), Ok(false));
+/// assert_eq!(queue.send(writer_handle, "a"), Ok(true));
+/// 
+/// assert_eq!(queue.read(), Ok("a"));
+/// 
+/// assert_eq!(queue.send(writer_handle, "a"), Ok(false));
+/// ```
+impl<T> DedupQueue<T> {
+    pub fn new() -> Self {
+        Self { inner: AsyncMsgQueue::new(), buffered: Mutex::new(HashSet::new()) }
+    }
+
+    pub fn new_arc() -> Arc<Self> { Arc::new(Self::new()) }
+
+    pub fn register_writer(&self) -> Result<WriterID, MsgQueueError> {
+        self.inner.register_writer()
+    }
+
+    pub fn deregister_writer(&self, id: WriterID) -> Result<(), MsgQueueError> {
+        self.inner.deregister_writer(id)
+    }
+
+    pub fn is_closed(&self) -> Result<bool, MsgQueueError> {
+        self.inner.is_closed()
+    }
+
+    pub fn is_terminated(&self) -> Result<bool, MsgQueueError> {
+        self.inner.is_terminated()
+    }
+
+    /// Enqueues a message unless an identical message is already buffered,
+    /// returning whether it was dropped as a duplicate
+    pub fn send(&self, id: WriterID, t: T) -> Result<bool, MsgQueueError>
+    where T: Clone + Eq + Hash {
+        self.inner.send_with(id, t, |queue, t| {
+            let mut buffered = crate::lock(&self.buffered)?;
+
+            if buffered.contains(&t) { return Ok(true) }
+
+            buffered.insert(t.clone());
+            queue.push(t);
+
+            Ok(false)
+        })?
+    }
+
+    /// Reads the next message from the queue
+    /// 
+    /// The set of buffered messages is locked before a message is popped,
+    /// so if that lock is poisoned the message is left in the queue
+    pub fn read(&self) -> Result<T, MsgQueueError> where T: Eq + Hash {
+        self.inner.read_with(|queue: &mut Queue<T>| {
+            let mut buffered = match crate::lock(&self.buffered) {
+                Ok(buffered) => buffered,
+                Err(e) => return Some(Err(e)),
+            };
+
+            let t = queue.pop()?;
+            buffered.remove(&t);
+
+            Some(Ok(t))
+        })?
+    }
+}
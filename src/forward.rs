@@ -0,0 +1,47 @@
+use std::sync::Arc;
+use std::thread::JoinHandle;
+
+use crate::{ AsyncMsgQueue, MsgQueueError, WriterID };
+
+/// The thread behind a combinator, which returns the error that stopped it, if any
+/// 
+/// Joining it tells a clean end of transmission apart from forwarding that failed,
+/// since either way the outputs close.
+pub type Forwarder = JoinHandle<Result<(), MsgQueueError>>;
+
+/// The queues a forwarder sends to, each with the writer it registered there
+pub(crate) type Outputs<O> = [(Arc<AsyncMsgQueue<O>>, WriterID)];
+
+/// Spawns a thread passing each message from `read` to `forward`, until the end of transmission
+/// 
+/// The thread stops at the end of transmission or at the first error from either closure,
+/// and then deregisters from every output, even if deregistering from an earlier one fails,
+/// so no output is left open with readers waiting on it.
+pub(crate) fn spawn_forwarder<M, O, R, F>(
+    outputs: Vec<(Arc<AsyncMsgQueue<O>>, WriterID)>,
+    mut read: R,
+    mut forward: F,
+) -> Forwarder
+where
+    O: Send + 'static,
+    R: FnMut() -> Result<M, MsgQueueError> + Send + 'static,
+    F: FnMut(M, &Outputs<O>) -> Result<(), MsgQueueError> + Send + 'static,
+{
+    std::thread::spawn(move || {
+        let result = loop {
+            let msg = match read() {
+                Ok(msg) => msg,
+                Err(e) if e.is_terminal() => break Ok(()),
+                Err(e) => break Err(e),
+            };
+
+            if let Err(e) = forward(msg, &outputs) { break Err(e) }
+        };
+
+        let deregistered = outputs.iter()
+            .map(|(output, writer)| output.deregister_writer(*writer))
+            .fold(Ok(()), Result::and);
+
+        result.and(deregistered)
+    })
+}
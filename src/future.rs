@@ -0,0 +1,107 @@
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::Arc;
+use std::task::{ Context, Poll };
+
+use crate::{ AsyncMsgQueue, MsgQueueError::*, QueueState };
+
+/// A future that resolves once the queue reaches a state,
+/// returned by `AsyncMsgQueue::closed` and `AsyncMsgQueue::terminated`
+/// 
+/// If the queue's locks are poisoned, the future resolves rather than waiting forever
+pub struct StateFuture<'a, T> {
+    queue: &'a AsyncMsgQueue<T>,
+    reached: fn(&QueueState) -> bool,
+}
+
+impl<'a, T> StateFuture<'a, T> {
+    pub(crate) fn new(queue: &'a AsyncMsgQueue<T>, reached: fn(&QueueState) -> bool) -> Self {
+        Self { queue, reached }
+    }
+}
+
+impl<T> Future for StateFuture<'_, T> {
+    type Output = ();
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<()> {
+        let Ok(state) = self.queue.lock_state() else { return Poll::Ready(()) };
+
+        if (self.reached)(&state) { return Poll::Ready(()) }
+
+        let Ok(mut wakers) = crate::lock(&self.queue.state_wakers) else { return Poll::Ready(()) };
+
+        if !wakers.iter().any(|waker| waker.will_wake(cx.waker())) {
+            wakers.push(cx.waker().clone());
+        }
+
+        Poll::Pending
+    }
+}
+
+/// A stream of batches of messages, returned by `AsyncMsgQueue::into_chunked_stream`
+/// 
+/// Each batch holds `chunk` messages, except the last, which flushes whatever is left once the
+/// queue terminates. Larger chunks mean fewer wake ups and less overhead per message, but a
+/// message may wait longer before it is yielded, as its batch fills up.
+/// 
+/// There's no `Stream` trait in `std`, so this has the same `poll_next` as `futures::Stream`,
+/// and `next_chunk` for awaiting each batch. The stream ends if the queue's locks are poisoned.
+pub struct ChunkStream<T> {
+    queue: Arc<AsyncMsgQueue<T>>,
+    chunk: usize,
+    batch: Vec<T>,
+}
+
+impl<T> ChunkStream<T> {
+    /// A `chunk` of 0 is treated as 1
+    pub(crate) fn new(queue: Arc<AsyncMsgQueue<T>>, chunk: usize) -> Self {
+        let chunk = chunk.max(1);
+        Self { queue, chunk, batch: Vec::with_capacity(chunk) }
+    }
+
+    /// Messages are moved in and out of the batch, so they must be `Unpin`
+    pub fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Vec<T>>> where T: Unpin {
+        let this = self.get_mut();
+        let queue = this.queue.clone();
+
+        let Ok(mut lock) = queue.lock_queue() else { return Poll::Ready(this.flush()) };
+
+        while this.batch.len() < this.chunk {
+            let wanted = this.chunk - this.batch.len();
+
+            match queue.pop_locked(&mut lock, |queue| queue.pop_up_to(wanted)) {
+                Ok(messages) => this.batch.extend(messages),
+                Err(NoMessages) => {
+                    let Ok(mut wakers) = crate::lock(&queue.read_wakers) else { break };
+
+                    if !wakers.iter().any(|waker| waker.will_wake(cx.waker())) {
+                        wakers.push(cx.waker().clone());
+                    }
+
+                    return Poll::Pending
+                },
+                Err(_) => break,
+            }
+        }
+
+        drop(lock);
+
+        Poll::Ready(this.flush())
+    }
+
+    /// Resolves to the next batch, or `None` once the stream has ended
+    pub fn next_chunk(&mut self) -> impl Future<Output = Option<Vec<T>>> + '_ where T: Unpin {
+        std::future::poll_fn(|cx| Pin::new(&mut *self).poll_next(cx))
+    }
+
+    /// Takes the batch so far, or `None` if it's empty
+    /// 
+    /// Messages are only transformed here, so it must be called without holding the queue lock
+    fn flush(&mut self) -> Option<Vec<T>> {
+        if self.batch.is_empty() { return None }
+
+        let batch = std::mem::replace(&mut self.batch, Vec::with_capacity(self.chunk));
+
+        Some(batch.into_iter().map(|t| self.queue.transform(t)).collect())
+    }
+}
@@ -0,0 +1,184 @@
+use rand::Rng;
+use std::collections::HashMap;
+use std::sync::{ Arc, Condvar, Mutex, MutexGuard };
+
+use crate::log::Log;
+use crate::{ MsgQueueError, MsgQueueError::*, ReaderID, WriterID };
+
+struct Groups<T> {
+    /// One cursor per group, shared by the group's readers
+    log: Log<T, String>,
+    /// The group each registered reader belongs to
+    members: HashMap<ReaderID, String>,
+}
+
+/// A message log read by independent consumer groups
+/// 
+/// Every group receives the full stream of messages, while the readers within a group
+/// compete for that group's messages, so each message is read once per group.
+/// Messages are discarded once every group has read them.
+/// 
+/// A group is created when its first reader joins, starting at the oldest retained message,
+/// and removed when its last reader leaves. When the last writer deregisters the queue closes,
+/// and once every group has reached the end of the closed queue it terminates.
+/// 
+/// ```
+/// use async_msg_queue::{
+///     ConsumerGroupQueue,
+///     MsgQueueError::*
+/// };
+/// 
+/// let queue = ConsumerGroupQueue::<usize>::new();
+/// 
+/// let audit = queue.join_group("audit").unwrap();
+/// let (worker_a, worker_b) = (queue.join_group("work").unwrap(), queue.join_group("work").unwrap());
+/// let writer = queue.register_writer().unwrap();
+/// 
+/// assert_eq!(queue.send(writer, 1), Ok(()));
+/// assert_eq!(queue.send(writer, 2), Ok(()));
+/// assert_eq!(queue.deregister_writer(writer), Ok(()));
+/// 
+/// assert_eq!(queue.read(worker_a), Ok(1));
+/// assert_eq!(queue.read(worker_b), Ok(2));
+/// assert_eq!(queue.read(worker_a), Err(EndOfTransmission));
+/// 
+/// assert_eq!(queue.read(audit), Ok(1));
+/// assert_eq!(queue.read(audit), Ok(2));
+/// assert_eq!(queue.read(audit), Err(EndOfTransmission));
+/// ```
+pub struct ConsumerGroupQueue<T> {
+    groups: Mutex<Groups<T>>,
+    readable: Condvar,
+}
+
+impl<T> Default for ConsumerGroupQueue<T> {
+    fn default() -> Self { Self::new() }
+}
+
+impl<T> ConsumerGroupQueue<T> {
+    pub fn new() -> Self {
+        Self {
+            groups: Mutex::new(Groups { log: Log::new(), members: HashMap::new() }),
+            readable: Condvar::new(),
+        }
+    }
+
+    pub fn new_arc() -> Arc<Self> { Arc::new(Self::new()) }
+
+    fn lock(&self) -> Result<MutexGuard<'_, Groups<T>>, MsgQueueError> {
+        crate::lock(&self.groups)
+    }
+
+    pub fn register_writer(&self) -> Result<WriterID, MsgQueueError> {
+        let id = rand::thread_rng().gen();
+
+        self.lock()?.log.writers.push(id);
+
+        Ok(id)
+    }
+
+    pub fn deregister_writer(&self, id: WriterID) -> Result<(), MsgQueueError> {
+        let mut groups = self.lock()?;
+        let log = &mut groups.log;
+
+        let index = log.writers.iter()
+            .position(|&writer| writer == id)
+            .ok_or(UnknownWriter)?;
+
+        log.writers.remove(index);
+
+        if log.writers.is_empty() && log.state.can_send() {
+            log.state.close();
+            log.check_terminated();
+            self.readable.notify_all();
+        }
+
+        Ok(())
+    }
+
+    /// Registers a reader in `group`, creating the group if it has no readers yet
+    pub fn join_group(&self, group: &str) -> Result<ReaderID, MsgQueueError> {
+        let id = rand::thread_rng().gen();
+
+        let mut groups = self.lock()?;
+        let start = groups.log.start;
+
+        groups.log.cursors.entry(group.to_string()).or_insert(start);
+        groups.members.insert(id, group.to_string());
+
+        Ok(id)
+    }
+
+    /// Deregisters a reader, removing its group if it was the last reader in it
+    pub fn leave_group(&self, reader: ReaderID) -> Result<(), MsgQueueError> {
+        let mut groups = self.lock()?;
+
+        let group = groups.members.remove(&reader).ok_or(UnknownReader)?;
+
+        if !groups.members.values().any(|member| *member == group) {
+            groups.log.cursors.remove(&group);
+            groups.log.compact(0);
+            groups.log.check_terminated();
+        }
+
+        Ok(())
+    }
+
+    pub fn is_closed(&self) -> Result<bool, MsgQueueError> {
+        Ok(!self.lock()?.log.state.can_send())
+    }
+
+    pub fn is_terminated(&self) -> Result<bool, MsgQueueError> {
+        Ok(!self.lock()?.log.state.can_read())
+    }
+
+    /// Appends a message for every group to read
+    /// 
+    /// If there are no groups, the message is discarded as nobody could read it
+    pub fn send(&self, id: WriterID, t: T) -> Result<(), MsgQueueError> {
+        let mut groups = self.lock()?;
+        let log = &mut groups.log;
+
+        if !log.writers.contains(&id) { return Err(UnknownWriter) }
+        if !log.state.can_send() { return Err(QueueClosed) }
+
+        log.entries.push_back(t);
+        log.compact(0);
+
+        self.readable.notify_all();
+
+        Ok(())
+    }
+
+    /// Reads the next message for the reader's group, which no other reader in the group will read
+    /// 
+    /// If the group has read every message, this function will wait for another
+    pub fn read(&self, reader: ReaderID) -> Result<T, MsgQueueError> where T: Clone {
+        let mut groups = self.lock()?;
+
+        loop {
+            if !groups.log.state.can_read() { return Err(QueueTerminated) }
+
+            let group = groups.members.get(&reader).ok_or(UnknownReader)?.clone();
+            let log = &mut groups.log;
+            let cursor = log.cursors[&group];
+
+            if let Some(t) = log.entries.get(cursor - log.start) {
+                let t = t.clone();
+
+                log.cursors.insert(group, cursor + 1);
+                log.compact(0);
+
+                return Ok(t)
+            }
+
+            if !log.state.can_send() {
+                log.check_terminated();
+                self.readable.notify_all();
+                return Err(EndOfTransmission)
+            }
+
+            groups = self.readable.wait(groups).map_err(|_| NoLock)?;
+        }
+    }
+}
@@ -0,0 +1,137 @@
+use std::ops::Deref;
+use std::sync::{ Arc, MutexGuard };
+use std::sync::atomic::{ AtomicU64, Ordering::Relaxed };
+
+use crate::{ AsyncMsgQueue, MsgQueueError, Queue, WriterID };
+
+/// A queue either borrowed or shared through an `Arc`
+enum QueueRef<'a, T> {
+    Borrowed(&'a AsyncMsgQueue<T>),
+    Shared(Arc<AsyncMsgQueue<T>>),
+}
+
+impl<T> Deref for QueueRef<'_, T> {
+    type Target = AsyncMsgQueue<T>;
+
+    fn deref(&self) -> &AsyncMsgQueue<T> {
+        match self {
+            QueueRef::Borrowed(queue) => queue,
+            QueueRef::Shared(queue) => queue,
+        }
+    }
+}
+
+/// A registered writer that deregisters itself when dropped
+/// 
+/// The guard also carries an optional name for the writer,
+/// and counts the messages sent through it.
+/// A guard from `register_writer_guard_arc` shares ownership of the queue,
+/// so it can be moved to another thread.
+pub struct WriterGuard<'a, T> {
+    queue: QueueRef<'a, T>,
+    id: WriterID,
+    name: Option<String>,
+    sent: AtomicU64,
+}
+
+impl<'a, T> WriterGuard<'a, T> {
+    pub(crate) fn new(queue: &'a AsyncMsgQueue<T>, id: WriterID) -> Self {
+        Self { queue: QueueRef::Borrowed(queue), id, name: None, sent: AtomicU64::new(0) }
+    }
+
+    pub(crate) fn new_shared(queue: Arc<AsyncMsgQueue<T>>, id: WriterID) -> WriterGuard<'static, T> {
+        WriterGuard { queue: QueueRef::Shared(queue), id, name: None, sent: AtomicU64::new(0) }
+    }
+
+    pub fn with_name(mut self, name: impl Into<String>) -> Self {
+        self.name = Some(name.into());
+        self
+    }
+
+    pub fn id(&self) -> WriterID {
+        self.id
+    }
+
+    pub fn name(&self) -> Option<&str> {
+        self.name.as_deref()
+    }
+
+    /// The number of messages sent through this guard
+    pub fn sent(&self) -> u64 {
+        self.sent.load(Relaxed)
+    }
+
+    /// Reserves buffer space for a burst of `additional` messages, see `AsyncMsgQueue::reserve`
+    pub fn reserve(&self, additional: usize) -> Result<(), MsgQueueError> {
+        self.queue.reserve(additional)
+    }
+
+    pub fn send(&self, t: T) -> Result<(), MsgQueueError> {
+        self.queue.send(self.id, t)?;
+        self.sent.fetch_add(1, Relaxed);
+        Ok(())
+    }
+
+    /// Sends every message in order, stopping at the first that fails
+    pub fn send_all<I: IntoIterator<Item = T>>(&self, messages: I) -> Result<(), MsgQueueError> {
+        messages.into_iter().try_for_each(|t| self.send(t))
+    }
+}
+
+/// Sends every message in order, like `send_all`
+/// 
+/// `extend` can't return an error, so once a message fails to send, it and the rest are dropped.
+/// Use `send_all` to find out whether every message was sent.
+impl<T> Extend<T> for WriterGuard<'_, T> {
+    fn extend<I: IntoIterator<Item = T>>(&mut self, messages: I) {
+        let _ = self.send_all(messages);
+    }
+}
+
+impl<T> Drop for WriterGuard<'_, T> {
+    fn drop(&mut self) {
+        let _ = self.queue.deregister_writer(self.id);
+    }
+}
+
+/// Exclusive access to a queue's buffer, returned by `AsyncMsgQueue::lock_for_drain`
+/// 
+/// While the guard is held no other reader can take a message and no writer can send one,
+/// so the holder sees the buffer exactly as it was when locked.
+/// Dropping the guard releases the lock.
+pub struct DrainGuard<'a, T> {
+    queue: &'a AsyncMsgQueue<T>,
+    lock: MutexGuard<'a, Queue<T>>,
+}
+
+impl<'a, T> DrainGuard<'a, T> {
+    pub(crate) fn new(queue: &'a AsyncMsgQueue<T>, lock: MutexGuard<'a, Queue<T>>) -> Self {
+        Self { queue, lock }
+    }
+
+    /// Takes the next message, following the same rules as `read` but never waiting
+    /// 
+    /// Returns `NoMessages` once the buffer is empty, or an end of transmission error
+    /// if the queue is also closed
+    pub fn pop(&mut self) -> Result<T, MsgQueueError> {
+        self.queue.pop_locked(&mut self.lock, Queue::pop)
+            .map(|t| self.queue.transform(t))
+    }
+
+    /// The number of messages left in the buffer
+    pub fn len(&self) -> usize {
+        self.lock.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
+impl<T> Iterator for DrainGuard<'_, T> {
+    type Item = T;
+
+    fn next(&mut self) -> Option<T> {
+        self.pop().ok()
+    }
+}
@@ -0,0 +1,41 @@
+use std::sync::Arc;
+
+use crate::{ AsyncMsgQueue, MsgQueueError };
+
+/// Reads messages until the end of transmission, returned by `AsyncMsgQueue::iter`
+/// 
+/// Each call to `next` waits for a message like `read`. The iterator ends at the end of
+/// transmission, or at the first other error, which `error` then returns.
+pub struct MsgQueueIter<T> {
+    queue: Arc<AsyncMsgQueue<T>>,
+    ended: bool,
+    error: Option<MsgQueueError>,
+}
+
+impl<T> MsgQueueIter<T> {
+    pub(crate) fn new(queue: Arc<AsyncMsgQueue<T>>) -> Self {
+        Self { queue, ended: false, error: None }
+    }
+
+    /// The error that ended the iterator early, if it didn't reach the end of transmission
+    pub fn error(&self) -> Option<&MsgQueueError> {
+        self.error.as_ref()
+    }
+}
+
+impl<T> Iterator for MsgQueueIter<T> {
+    type Item = T;
+
+    fn next(&mut self) -> Option<T> {
+        if self.ended { return None }
+
+        match self.queue.read() {
+            Ok(t) => Some(t),
+            Err(e) => {
+                self.ended = true;
+                if !e.is_terminal() { self.error = Some(e) }
+                None
+            },
+        }
+    }
+}
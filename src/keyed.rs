@@ -0,0 +1,70 @@
+use std::sync::Arc;
+
+use crate::{ AsyncMsgQueue, MsgQueueError, WriterID };
+
+/// A message queue that only keeps the latest unread value for each key
+/// 
+/// Sending a message with the same key as a buffered message replaces the
+/// buffered value in place, so the new value keeps the position of the old one.
+/// Readers therefore see at most one value per key until that value is read.
+pub struct KeyedQueue<K, T> {
+    inner: AsyncMsgQueue<(K, T)>,
+}
+
+impl<K, T> Default for KeyedQueue<K, T> {
+    fn default() -> Self { Self::new() }
+}
+
+/// ```
+/// use async_msg_queue::KeyedQueue;
+/// 
+/// let queue = KeyedQueue::<&str, usize>::new();
+/// 
+/// let writer_handle = queue.register_writer().unwrap();
+/// 
+/// assert_eq!(queue.send_keyed(writer_handle, "a", 1), Ok(()));
+/// assert_eq!(queue.send_keyed(writer_handle, "b", 2), Ok(()));
+/// assert_eq!(queue.send_keyed(writer_handle, "a", 3), Ok(()));
+/// 
+/// assert_eq!(queue.read(), Ok(("a", 3)));
+/// assert_eq!(queue.read(), Ok(("b", 2)));
+/// ```
+impl<K, T> KeyedQueue<K, T> {
+    pub fn new() -> Self {
+        Self { inner: AsyncMsgQueue::new() }
+    }
+
+    pub fn new_arc() -> Arc<Self> { Arc::new(Self::new()) }
+
+    pub fn register_writer(&self) -> Result<WriterID, MsgQueueError> {
+        self.inner.register_writer()
+    }
+
+    pub fn deregister_writer(&self, id: WriterID) -> Result<(), MsgQueueError> {
+        self.inner.deregister_writer(id)
+    }
+
+    pub fn is_closed(&self) -> Result<bool, MsgQueueError> {
+        self.inner.is_closed()
+    }
+
+    pub fn is_terminated(&self) -> Result<bool, MsgQueueError> {
+        self.inner.is_terminated()
+    }
+
+    /// Enqueues a message, replacing any unread message with the same key
+    pub fn send_keyed(&self, id: WriterID, key: K, t: T) -> Result<(), MsgQueueError>
+    where K: PartialEq {
+        self.inner.send_with(id, (key, t), |queue, (key, t)| {
+            match queue.find_mut(|(k, _)| *k == key) {
+                Some((_, v)) => *v = t,
+                None => queue.push((key, t)),
+            }
+        })
+    }
+
+    /// Reads the next message and its key from the queue
+    pub fn read(&self) -> Result<(K, T), MsgQueueError> {
+        self.inner.read()
+    }
+}
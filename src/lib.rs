@@ -1,91 +1,544 @@
 use rand::Rng;
-use std::sync::{ Arc, Mutex };
+use std::collections::{ BTreeMap, HashMap, VecDeque };
+use std::sync::{ Arc, Condvar, Mutex, MutexGuard };
+use std::sync::atomic::{ AtomicBool, AtomicU64, AtomicUsize, Ordering::Relaxed };
+use std::time::{ Duration, Instant };
+
+use deadline::DeadlineTimer;
+use forward::spawn_forwarder;
+
+mod cancel;
+mod channel;
+mod coalesce;
+mod deadline;
+mod dedup;
+mod forward;
+#[cfg(feature = "async")]
+mod future;
+mod group;
+mod guard;
+mod iter;
+mod keyed;
+mod local;
+mod log;
+mod metrics;
+mod router;
+mod sharded;
+mod state;
+
+pub mod compat;
+
+#[cfg(feature = "bench")]
+pub mod bench;
+
+pub use cancel::CancelToken;
+pub use channel::{ channel, Reader, Writer };
+pub use coalesce::CoalesceQueue;
+pub use dedup::DedupQueue;
+pub use forward::Forwarder;
+#[cfg(feature = "async")]
+pub use future::{ ChunkStream, StateFuture };
+pub use group::ConsumerGroupQueue;
+pub use guard::{ DrainGuard, WriterGuard };
+pub use iter::MsgQueueIter;
+pub use keyed::KeyedQueue;
+pub use local::LocalMsgQueue;
+pub use log::{ LogQueue, SharedLogQueue };
+pub use metrics::QueueMetrics;
+pub use router::Router;
+pub use sharded::ShardedMsgQueue;
+pub use state::QueueState;
 
 #[cfg(test)]
+#[allow(clippy::useless_vec)]
 mod tests;
 
+/// Locks a mutex, treating a poisoned lock as `NoLock`
+/// 
+/// Every lock in the crate goes through here, so the policy for poisoned locks lives in one place
+fn lock<M>(mutex: &Mutex<M>) -> Result<MutexGuard<'_, M>, MsgQueueError> {
+    mutex.lock().map_err(|_| NoLock)
+}
+
+/// The priority of messages sent without one
+pub const DEFAULT_PRIORITY: u8 = 0;
+
+/// How many sends in a row must find an adaptive queue at its capacity target before the target grows
+pub const CAPACITY_GROWTH_HITS: usize = 4;
+
+/// How often `read_until` re-checks its cancellation flag while parked
+pub const CANCEL_CHECK_INTERVAL: Duration = Duration::from_millis(10);
+
+/// The number of messages missed between two sequence numbers from `send_seq` read in turn
+/// 
+/// Consecutive numbers have a gap of 0, as does a repeated number. The difference wraps
+/// around, so a sequence that overflows `u64` doesn't report an enormous gap.
+pub fn seq_gap(prev: u64, curr: u64) -> u64 {
+    curr.wrapping_sub(prev).saturating_sub(1)
+}
+
+type PoisonPill<T> = Box<dyn Fn(&T) -> bool + Send + Sync>;
+
+type SendValidator<T> = Box<dyn Fn(&T) -> bool + Send + Sync>;
+
+#[cfg(feature = "testing")]
+type SendBarrier = Box<dyn Fn() + Send + Sync>;
+
+/// Key/value metadata sent alongside a message
+pub type Headers = HashMap<String, String>;
+
+/// A buffered message
+struct Slot<T> {
+    /// The epoch the message was sent in
+    epoch: u64,
+    /// Orders the message among all those pushed, to either end, so the oldest can be found
+    order: u64,
+    /// Boxed, and `None` when empty, so messages without headers don't allocate
+    headers: Option<Box<Headers>>,
+    t: T,
+}
+
+/// Messages are buffered oldest first, after any pushed to the front, most recent first
 struct Queue<T> {
-    vec: Vec<T>
+    deque: VecDeque<Slot<T>>,
+    /// Incremented whenever the queue is cleared
+    epoch: u64,
+    /// The number of messages ever pushed
+    pushed: u64,
+    /// The number of messages ever pushed, including those pushed to the front
+    inserted: u64,
+    /// The number of messages ever removed, whether read or discarded,
+    /// not counting those pushed to the front
+    removed: u64,
+    /// The number of messages at the front that were pushed there
+    front_len: usize,
+    /// Matches messages that should close the queue when popped
+    poison_pill: Option<PoisonPill<T>>,
+    /// Set when a poison pill is popped, until the queue is closed
+    poisoned: bool,
+    /// Whether the oldest or newest message is popped first
+    ordering: ReadOrder,
+    /// The order of the oldest message discarded when the queue was force terminated, if any was
+    abandoned: Option<u64>,
 }
 
 impl<T> Queue<T> {
     fn new() -> Self {
-        Self { vec: Vec::new() }
+        Self {
+            deque: VecDeque::new(),
+            epoch: 0,
+            pushed: 0,
+            inserted: 0,
+            removed: 0,
+            front_len: 0,
+            poison_pill: None,
+            poisoned: false,
+            ordering: ReadOrder::Fifo,
+            abandoned: None,
+        }
     }
 
     fn push(&mut self, t: T) {
-        self.vec.insert(0, t)
+        self.push_with_headers(t, Headers::new())
+    }
+
+    fn push_with_headers(&mut self, t: T, headers: Headers) {
+        let headers = (!headers.is_empty()).then(|| Box::new(headers));
+
+        self.pushed += 1;
+        self.inserted += 1;
+        self.deque.push_back(Slot { epoch: self.epoch, order: self.inserted, headers, t })
+    }
+
+    /// Pushes a message so that it is the next to be popped
+    /// 
+    /// These messages are kept out of `pushed` and `removed`, as they skip any barriers
+    fn push_front(&mut self, t: T) {
+        self.front_len += 1;
+        self.inserted += 1;
+        self.deque.push_front(Slot { epoch: self.epoch, order: self.inserted, headers: None, t })
     }
 
     fn pop(&mut self) -> Option<T> {
-        self.vec.pop()
+        self.pop_slot().map(|slot| slot.t)
     }
-}
 
-/// The message queue can be in one of three states:
-/// - Open - The message queue can recieve more messages
-/// - Closed - The message queue can recieve no more messages
-/// - Terminated - The message queue has been closed and all the messages have been read
-#[derive(PartialEq)]
-enum MsgQueueState {
-    Open,
-    Closed,
-    Terminated,
-}
+    fn pop_with_epoch(&mut self) -> Option<(u64, T)> {
+        self.pop_slot().map(|slot| (slot.epoch, slot.t))
+    }
+
+    fn pop_with_headers(&mut self) -> Option<(T, Headers)> {
+        self.pop_slot().map(|slot| (slot.t, slot.headers.map(|headers| *headers).unwrap_or_default()))
+    }
+
+    /// Pops the next message, skipping over any poison pills
+    fn pop_slot(&mut self) -> Option<Slot<T>> {
+        loop {
+            let slot = match self.ordering {
+                ReadOrder::Lifo if self.front_len == 0 => self.pop_lifo()?,
+                _ => self.pop_fifo()?,
+            };
+
+            if self.is_pill(&slot.t) {
+                self.poisoned = true;
+                continue
+            }
+
+            return Some(slot)
+        }
+    }
+
+    fn is_pill(&self, t: &T) -> bool {
+        self.poison_pill.as_ref().is_some_and(|is_pill| is_pill(t))
+    }
+
+    /// Pops the oldest message, or the newest of those pushed to the front
+    fn pop_fifo(&mut self) -> Option<Slot<T>> {
+        let t = self.deque.pop_front()?;
+
+        if self.front_len > 0 {
+            self.front_len -= 1
+        } else {
+            self.removed += 1
+        }
+
+        Some(t)
+    }
+
+    /// Pops the newest message, ignoring those pushed to the front
+    fn pop_lifo(&mut self) -> Option<Slot<T>> {
+        if self.deque.len() == self.front_len { return None }
+
+        self.removed += 1;
+        self.deque.pop_back()
+    }
+
+    /// Removes the message that was pushed longest ago, whichever end it was pushed to
+    fn pop_oldest(&mut self) -> Option<Slot<T>> {
+        // Normal messages are oldest first and those pushed to the front newest first,
+        // so the oldest is at one side of the boundary between them
+        let oldest_front = self.front_len.checked_sub(1);
+        let oldest_back = Some(self.front_len).filter(|&i| i < self.deque.len());
+
+        let index = match (oldest_front, oldest_back) {
+            (Some(front), Some(back)) if self.deque[front].order < self.deque[back].order => front,
+            (_, Some(back)) => back,
+            (Some(front), None) => front,
+            (None, None) => return None,
+        };
+
+        if index < self.front_len {
+            self.front_len -= 1
+        } else {
+            self.removed += 1
+        }
+
+        self.deque.remove(index)
+    }
+
+    /// Pops up to `max` messages in order, returning `None` if there were none
+    fn pop_up_to(&mut self, max: usize) -> Option<Vec<T>> {
+        let batch: Vec<T> = std::iter::from_fn(|| self.pop()).take(max).collect();
+        if batch.is_empty() { None } else { Some(batch) }
+    }
+
+    fn len(&self) -> usize {
+        self.deque.len()
+    }
+
+    fn clear(&mut self) {
+        self.removed += (self.deque.len() - self.front_len) as u64;
+        self.front_len = 0;
+        self.deque.clear()
+    }
+
+    /// Removes every message matching `pred`, returning them in the order they would be popped
+    /// 
+    /// Poison pills are left in place, without being passed to `pred`
+    fn extract<F: FnMut(&T) -> bool>(&mut self, mut pred: F) -> Vec<T> {
+        let deque = std::mem::take(&mut self.deque);
+        let front_len = self.front_len;
+
+        let mut extracted = vec![];
+
+        for (i, slot) in deque.into_iter().enumerate() {
+            if self.is_pill(&slot.t) || !pred(&slot.t) {
+                self.deque.push_back(slot);
+                continue
+            }
+
+            if i < front_len {
+                self.front_len -= 1
+            } else {
+                self.removed += 1
+            }
+
+            extracted.push(slot.t)
+        }
+
+        extracted
+    }
+
+    /// Iterates over every message in the order they would be popped, skipping over any poison pills
+    fn iter(&self) -> impl Iterator<Item = &T> {
+        let (front, rest) = (self.deque.range(..self.front_len), self.deque.range(self.front_len..));
+        let fifo = self.ordering == ReadOrder::Fifo;
 
-impl MsgQueueState {
-    pub fn new() -> Self { Self::Open }
+        front
+            .chain(rest.clone().filter(move |_| fifo))
+            .chain(rest.rev().filter(move |_| !fifo))
+            .map(|slot| &slot.t)
+            .filter(|t| !self.is_pill(t))
+    }
 
-    fn close(&mut self) {
-        *self = Self::Closed
+    /// The message the next `pop` would return
+    fn peek(&self) -> Option<&T> {
+        self.iter().next()
     }
 
-    fn terminate(&mut self) {
-        *self = Self::Terminated
+    /// Every message in the order they would be popped, without any poison pills
+    fn into_vec(mut self) -> Vec<T> {
+        let rest = self.deque.split_off(self.front_len);
+        let rest: Vec<Slot<T>> = match self.ordering {
+            ReadOrder::Fifo => rest.into_iter().collect(),
+            ReadOrder::Lifo => rest.into_iter().rev().collect(),
+        };
+
+        let is_pill = self.poison_pill.take();
+
+        self.deque.into_iter().chain(rest)
+            .map(|slot| slot.t)
+            .filter(|t| !is_pill.as_ref().is_some_and(|is_pill| is_pill(t)))
+            .collect()
     }
 
-    fn can_send(&self) -> bool {
-        *self == Self::Open
+    /// Discards every message and starts a new epoch
+    fn reset(&mut self) {
+        self.clear();
+        self.epoch += 1
     }
 
-    fn can_read(&self) -> bool {
-        *self != Self::Terminated
+    /// Finds the newest message matching `f`, skipping over any poison pills
+    fn find_mut<F: FnMut(&&mut T) -> bool>(&mut self, mut f: F) -> Option<&mut T> {
+        let is_pill = &self.poison_pill;
+
+        self.deque.iter_mut().rev()
+            .map(|slot| &mut slot.t)
+            .find(|t| !is_pill.as_ref().is_some_and(|is_pill| is_pill(t)) && f(t))
     }
 }
 
+/// How `read` waits for a message when the queue is empty:
+/// - Spin - Retry continuously until a message arrives, occupying a core while it waits
+/// - Park - Sleep until a writer sends a message or the queue closes, which is the default
+/// - SpinThenPark - Spin for up to `spin`, then park
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum ReadStrategy {
+    Spin,
+    Park,
+    SpinThenPark { spin: Duration },
+}
+
+/// Why a queue closed:
+/// - LastWriterDeregistered - The last registered writer deregistered
+/// - PoisonPill - A reader read the queue's poison pill
+/// - Terminated - The queue was terminated while it was still open
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum CloseReason {
+    LastWriterDeregistered,
+    PoisonPill,
+    Terminated,
+}
+
+/// How long a send waits for space in a full queue with the `Block` policy
+#[derive(Clone, Copy)]
+enum SendWait {
+    Never,
+    Forever,
+    Until(Instant),
+}
+
+/// Which parked readers are woken when a message is sent:
+/// - One - A single reader, since only one can take the message
+/// - All - Every reader, for example when readers take messages selectively
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum WakeStrategy {
+    One,
+    All,
+}
+
+/// What `send` does when a bounded queue is full:
+/// - Block - Wait until a reader makes space
+/// - Error - Return `QueueFull`
+/// - DropOldest - Discard the unread message sent longest ago to make space, even if it was sent with `send_front`
+/// - DropNewest - Discard the message being sent, and return `Ok`
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum OverflowPolicy {
+    Block,
+    Error,
+    DropOldest,
+    DropNewest,
+}
+
+/// Which message `read` returns first:
+/// - Fifo - The oldest message, like a queue
+/// - Lifo - The newest message, like a stack
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum ReadOrder {
+    Fifo,
+    Lifo,
+}
+
 // TODO: add more information to MsgQueueError
 #[derive(PartialEq, Debug)]
 pub enum MsgQueueError {
     NoLock,
     NoMessages,
     QueueClosed,
+    QueueFull,
     UnknownWriter,
+    UnknownReader,
+    OffsetUnavailable,
     NegativeWriters,
+    NegativeReaders,
     QueueTerminated,
     EndOfTransmission,
+    Cancelled,
+    Timeout,
+    Rejected,
 } use MsgQueueError::*;
 
 impl MsgQueueError {
+    /// Whether the error means there will never be another message to read
+    /// 
+    /// `EndOfTransmission` is returned to the reader that finds the closed queue empty,
+    /// and `QueueTerminated` to every read after that, but both mean the stream has ended
+    pub fn is_terminal(&self) -> bool {
+        matches!(self, EndOfTransmission | QueueTerminated)
+    }
+}
+
+impl MsgQueueError {
+    #[allow(clippy::inherent_to_string)]
     pub fn to_string(&self) -> String {
         match self {
             NoLock => "Failed to get mutex lock".into(),
             NoMessages => "No messages to read".into(),
             QueueClosed => "Cannot send to closed queue".into(),
+            QueueFull => "Cannot send to full queue".into(),
             UnknownWriter => "Unrecognised writer".into(),
+            UnknownReader => "Unrecognised reader".into(),
+            OffsetUnavailable => "Log offset has been discarded or does not exist yet".into(),
             NegativeWriters => "Cannot have fewer than 1 writers to a queue".into(),
+            NegativeReaders => "Cannot have fewer than 1 readers from a queue".into(),
             QueueTerminated => "Cannot read from terminated queue".into(),
             EndOfTransmission => "Message queue reached end of transmission".into(),
+            Cancelled => "Read was cancelled".into(),
+            Timeout => "Timed out waiting for a message".into(),
+            Rejected => "Message was rejected by the send validator".into(),
         }
     }
 }
 
+/// Why `read_or_status` didn't return a message:
+/// - WouldBlock - The queue is empty, but `writers_remaining` writers could still send
+/// - Ended - The queue has reached the end of transmission
+/// - Failed - Reading failed for some other reason
+#[derive(PartialEq, Debug)]
+pub enum ReadStatus {
+    WouldBlock { writers_remaining: usize },
+    Ended,
+    Failed(MsgQueueError),
+}
+
+impl From<MsgQueueError> for ReadStatus {
+    fn from(e: MsgQueueError) -> Self {
+        if e.is_terminal() { Self::Ended } else { Self::Failed(e) }
+    }
+}
+
+/// Why `try_read_status` didn't return a message:
+/// - OpenEmpty - The queue is empty, but still open, so more messages may arrive
+/// - ClosedDraining - The queue is closed and empty, but hasn't terminated yet,
+///   as it is waiting for readers to deregister (see `with_min_readers`)
+/// - Terminated - The queue has reached the end of transmission
+/// - Failed - Reading failed for some other reason
+#[derive(PartialEq, Debug)]
+pub enum EmptyStatus {
+    OpenEmpty,
+    ClosedDraining,
+    Terminated,
+    Failed(MsgQueueError),
+}
+
+impl From<MsgQueueError> for EmptyStatus {
+    fn from(e: MsgQueueError) -> Self {
+        if e.is_terminal() { Self::Terminated } else { Self::Failed(e) }
+    }
+}
+
 type WriterID = usize;
 
-// TODO: Add names to message queues
+pub type ReaderID = usize;
+
+/// Marks a point in the queue, returned by `AsyncMsgQueue::barrier`
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct BarrierToken(u64);
+
 pub struct AsyncMsgQueue<T> {
+    /// Identifies the queue in logs, `"unnamed"` unless created with `new_named`
+    name: String,
     queue: Mutex<Queue<T>>,
-    state: Mutex<MsgQueueState>,
+    readable: Condvar,
+    /// Notified when a bounded queue has space for another message
+    writable: Condvar,
+    parked_readers: AtomicUsize,
+    sent: AtomicU64,
+    read: AtomicU64,
+    dropped: AtomicU64,
+    /// Wakers for futures waiting on the state, always locked after `state`
+    #[cfg(feature = "async")]
+    state_wakers: Mutex<Vec<std::task::Waker>>,
+    /// Wakers for streams waiting on a message, always locked after `queue`
+    #[cfg(feature = "async")]
+    read_wakers: Mutex<Vec<std::task::Waker>>,
+    #[cfg(feature = "metrics")]
+    lock_contention: AtomicU64,
+    /// Called by sends between the state check and the push, always locked after `queue`
+    #[cfg(feature = "testing")]
+    send_barrier: Mutex<Option<SendBarrier>>,
+    state: Mutex<QueueState>,
+    /// Why the queue closed, always locked after `state`
+    close_reason: Mutex<Option<CloseReason>>,
     writers: Mutex<Vec<WriterID>>,
+    heartbeats: Mutex<HashMap<WriterID, Instant>>,
+    /// When each registered reader last read a message
+    readers: Mutex<HashMap<ReaderID, Instant>>,
+    /// The number of registered readers waiting in `read_as`, which don't count towards `min_readers`
+    readers_reading: AtomicUsize,
+    writer_timeout: Option<Duration>,
+    /// Registered readers that may remain for a closed, empty queue to terminate
+    min_readers: Option<usize>,
+    deadline: DeadlineTimer,
+    read_strategy: ReadStrategy,
+    wake_strategy: WakeStrategy,
+    poll_interval: Duration,
+    capacity: Option<usize>,
+    /// The `(min, max)` bounds of an adaptive capacity
+    adaptive_capacity: Option<(usize, usize)>,
+    /// The soft capacity of an adaptive queue, only changed while holding the queue lock
+    capacity_target: AtomicUsize,
+    /// Sends in a row that found an adaptive queue at its target, only changed while holding the queue lock
+    capacity_hits: AtomicUsize,
+    overflow_policy: OverflowPolicy,
+    read_transform: Option<Box<dyn Fn(T) -> T + Send + Sync>>,
+    send_validator: Option<SendValidator<T>>,
+    metrics: Option<Arc<dyn QueueMetrics>>,
+    backpressure_hook: Option<Box<dyn Fn(Duration) + Send + Sync>>,
+}
+
+impl<T> Default for AsyncMsgQueue<T> {
+    fn default() -> Self { Self::new() }
 }
 
 /// ```
@@ -137,127 +590,1802 @@ pub struct AsyncMsgQueue<T> {
 impl<T> AsyncMsgQueue<T> {
     pub fn new() -> Self {
         Self {
+            name: "unnamed".to_string(),
             queue: Mutex::new(Queue::new()),
-            state: Mutex::new(MsgQueueState::new()),
-            writers: Mutex::new(Vec::new())
+            readable: Condvar::new(),
+            writable: Condvar::new(),
+            parked_readers: AtomicUsize::new(0),
+            sent: AtomicU64::new(0),
+            read: AtomicU64::new(0),
+            dropped: AtomicU64::new(0),
+            #[cfg(feature = "async")]
+            state_wakers: Mutex::new(Vec::new()),
+            #[cfg(feature = "async")]
+            read_wakers: Mutex::new(Vec::new()),
+            #[cfg(feature = "metrics")]
+            lock_contention: AtomicU64::new(0),
+            #[cfg(feature = "testing")]
+            send_barrier: Mutex::new(None),
+            state: Mutex::new(QueueState::new()),
+            close_reason: Mutex::new(None),
+            writers: Mutex::new(Vec::new()),
+            heartbeats: Mutex::new(HashMap::new()),
+            readers: Mutex::new(HashMap::new()),
+            readers_reading: AtomicUsize::new(0),
+            writer_timeout: None,
+            min_readers: None,
+            deadline: DeadlineTimer::default(),
+            read_strategy: ReadStrategy::Park,
+            wake_strategy: WakeStrategy::One,
+            poll_interval: Duration::ZERO,
+            capacity: None,
+            adaptive_capacity: None,
+            capacity_target: AtomicUsize::new(0),
+            capacity_hits: AtomicUsize::new(0),
+            overflow_policy: OverflowPolicy::Error,
+            read_transform: None,
+            send_validator: None,
+            metrics: None,
+            backpressure_hook: None,
         }
     }
 
-    fn new_writer_id(&self) -> WriterID {
-        rand::thread_rng().gen()
+    /// Sets how `read` waits when there are no messages, which is parking by default
+    pub fn with_read_strategy(mut self, strategy: ReadStrategy) -> Self {
+        self.read_strategy = strategy;
+        self
     }
 
-    fn check_writer(&self, id: WriterID) -> Result<(), MsgQueueError> {
-        if self.writers
-            .lock().map_err(|_| NoLock)?
-            .contains(&id)
-        {
-            Ok(())
-        } else {
-            Err(UnknownWriter)
+    /// Sets how many parked readers a send wakes
+    /// 
+    /// Closing or terminating the queue always wakes every parked reader, whatever the strategy
+    pub fn with_wake_strategy(mut self, strategy: WakeStrategy) -> Self {
+        self.wake_strategy = strategy;
+        self
+    }
+
+    /// Sets how long a spinning `read` sleeps between retries
+    /// 
+    /// A zero interval, the default, retries immediately
+    pub fn with_poll_interval(mut self, interval: Duration) -> Self {
+        self.poll_interval = interval;
+        self
+    }
+
+    /// Applies `f` to every message as it is read
+    /// 
+    /// `f` runs once per message, on whichever reader receives it,
+    /// after the queue has been unlocked, except for reads through a `DrainGuard`, which holds the lock
+    pub fn with_read_transform<F: Fn(T) -> T + Send + Sync + 'static>(mut self, f: F) -> Self {
+        self.read_transform = Some(Box::new(f));
+        self
+    }
+
+    /// Applied to every message read, once the queue lock is released where possible
+    fn transform(&self, t: T) -> T {
+        self.report(|metrics| metrics.on_read());
+
+        match &self.read_transform {
+            Some(f) => f(t),
+            None => t,
         }
     }
 
-    pub fn register_writer(&self) -> Result<WriterID, MsgQueueError> {
-        let id = self.new_writer_id();
+    /// Sets which message readers receive first
+    /// 
+    /// Messages sent with `send_front` are still read next, and `DropOldest` still drops the oldest.
+    /// Barriers assume `Fifo` ordering, so with `Lifo` they may be reached early.
+    pub fn with_ordering(mut self, ordering: ReadOrder) -> Self {
+        if let Ok(queue) = self.queue.get_mut() {
+            queue.ordering = ordering;
+        }
+
+        self
+    }
+
+    /// Closes the queue when a reader pops a message equal to `pill`
+    /// 
+    /// The pill itself is never returned to a reader. It closes the queue for every reader
+    /// and writer, but messages already queued behind it are still delivered
+    /// before readers see the end of transmission.
+    pub fn with_poison_pill(mut self, pill: T) -> Self where T: PartialEq + Send + Sync + 'static {
+        if let Ok(queue) = self.queue.get_mut() {
+            queue.poison_pill = Some(Box::new(move |t| *t == pill));
+        }
+
+        self
+    }
+
+    /// Reports the queue's sends, reads and state changes to `metrics`, see `QueueMetrics`
+    pub fn with_metrics(mut self, metrics: Arc<dyn QueueMetrics>) -> Self {
+        self.metrics = Some(metrics);
+        self
+    }
+
+    fn report<F: FnOnce(&dyn QueueMetrics)>(&self, f: F) {
+        if let Some(metrics) = &self.metrics { f(metrics.as_ref()) }
+    }
+
+    /// Rejects any message for which `f` returns false, so sends return `Rejected`
+    /// 
+    /// `f` runs after checking the writer is registered and the queue is open, but before
+    /// taking the queue lock, so it can't deadlock the queue and may even use it.
+    /// Sends that return `T` with their errors, like `send_returning`, hand the rejected message back.
+    pub fn with_send_validator<F: Fn(&T) -> bool + Send + Sync + 'static>(mut self, f: F) -> Self {
+        self.send_validator = Some(Box::new(f));
+        self
+    }
+
+    /// Limits the number of unread messages, past which `send` returns `QueueFull`
+    pub fn with_capacity(self, capacity: usize) -> Self {
+        self.with_capacity_policy(capacity, OverflowPolicy::Error)
+    }
+
+    /// Limits the number of unread messages, past which `send` follows `policy`
+    pub fn with_capacity_policy(mut self, capacity: usize, policy: OverflowPolicy) -> Self {
+        self.capacity = Some(capacity);
+        self.overflow_policy = policy;
+        self
+    }
+
+    /// Bounds the queue with a soft capacity target that adapts to throughput
+    /// 
+    /// The target starts at `min`, and a send that finds the queue at the target waits or fails
+    /// as with `OverflowPolicy::Block`. Once `CAPACITY_GROWTH_HITS` sends in a row have found it
+    /// there, the target doubles, up to `max`, and that send goes through instead. Whenever a read
+    /// empties the queue, the target halves, down to `min`, and the buffer is shrunk to fit it.
+    /// So the target only grows while producers consistently hit the limit, and shrinks back
+    /// while readers keep up.
+    pub fn with_adaptive_capacity(self, min: usize, max: usize) -> Self {
+        let max = max.max(min);
+        let mut queue = self.with_capacity_policy(max, OverflowPolicy::Block);
+
+        queue.adaptive_capacity = Some((min, max));
+        queue.capacity_target = AtomicUsize::new(min);
+        queue
+    }
+
+    /// The current soft capacity of an adaptive queue, or `None` if it doesn't adapt
+    pub fn capacity_target(&self) -> Option<usize> {
+        self.adaptive_capacity.map(|_| self.capacity_target.load(Relaxed))
+    }
+
+    /// Calls `cb` whenever a `send` had to wait for space, with how long it waited
+    /// 
+    /// This only fires for the `Block` policy, once the send has made room for its message.
+    /// `cb` is called while holding the queue lock, so it mustn't use the queue.
+    pub fn on_backpressure<F: Fn(Duration) + Send + Sync + 'static>(mut self, cb: F) -> Self {
+        self.backpressure_hook = Some(Box::new(cb));
+        self
+    }
+
+    /// The maximum number of unread messages, or `None` if the queue is unbounded
+    pub fn capacity(&self) -> Option<usize> {
+        self.capacity
+    }
+
+    /// The number of messages that can be sent before the queue is full,
+    /// or `None` if the queue is unbounded
+    pub fn remaining_capacity(&self) -> Result<Option<usize>, MsgQueueError> {
+        let Some(capacity) = self.capacity else { return Ok(None) };
+
+        let len = self.lock_queue()?.len();
+
+        Ok(Some(capacity.saturating_sub(len)))
+    }
 
-        self.writers
-            .lock().map_err(|_| NoLock)?
-            .push(id);
+    /// The number of unread messages at each priority level
+    /// 
+    /// Queues don't have a priority mode yet, so every message is at `DEFAULT_PRIORITY`
+    /// and this always returns a single entry for it
+    pub fn len_by_priority(&self) -> Result<BTreeMap<u8, usize>, MsgQueueError> {
+        let len = self.lock_queue()?.len();
+
+        Ok(BTreeMap::from([(DEFAULT_PRIORITY, len)]))
+    }
+
+    /// Registers a reader, so that `starved_readers` can track when it last read a message
+    /// 
+    /// Registered readers must read with `read_as` to be tracked.
+    /// Readers don't need to be registered to use `read`.
+    pub fn register_reader(&self) -> Result<ReaderID, MsgQueueError> {
+        let id = rand::thread_rng().gen();
+
+        lock(&self.readers)?
+            .insert(id, Instant::now());
 
         Ok(id)
     }
 
-    pub fn deregister_writer(&self, id: WriterID) -> Result<(), MsgQueueError> {
-        let mut writers = self.writers
-            .lock().map_err(|_| NoLock)?;
+    pub fn deregister_reader(&self, id: ReaderID) -> Result<(), MsgQueueError> {
+        lock(&self.readers)?
+            .remove(&id)
+            .ok_or(UnknownReader)?;
 
-        let index = writers.iter()
-            .position(|&writer| writer == id)
-            .ok_or(UnknownWriter)?;
+        if self.min_readers.is_some() { self.wake_readers()? }
 
-        writers.remove(index);
+        Ok(())
+    }
+
+    /// Holds off terminating a closed, empty queue until at most `n` registered readers remain,
+    /// besides those already waiting in `read_as`
+    /// 
+    /// Until then, readers that find the queue closed and empty wait as if it were open,
+    /// and are woken to re-check whenever a reader deregisters. Readers that never
+    /// registered don't count, so late-joining readers should register before the queue closes.
+    /// Readers waiting in `read_as` don't count either, as they can't deregister until
+    /// their read returns, so a registered reader never waits for itself.
+    /// `peek` likewise only reports the end of transmission once the queue may terminate.
+    pub fn with_min_readers(mut self, n: usize) -> Self {
+        self.min_readers = Some(n);
+        self
+    }
 
-        if writers.is_empty() {
-            self.close()?
+    /// Whether a closed, empty queue may terminate, given the queue's `min_readers`
+    fn readers_allow_termination(&self) -> Result<bool, MsgQueueError> {
+        let Some(n) = self.min_readers else { return Ok(true) };
+
+        let idle = lock(&self.readers)?.len().saturating_sub(self.readers_reading.load(Relaxed));
+
+        Ok(idle <= n)
+    }
+
+    /// Reads the next message as a registered reader, recording when it was read
+    pub fn read_as(&self, reader: ReaderID) -> Result<T, MsgQueueError> {
+        if !lock(&self.readers)?.contains_key(&reader) {
+            return Err(UnknownReader)
         }
 
-        Ok(())
+        self.readers_reading.fetch_add(1, Relaxed);
+        let t = self.read();
+        self.readers_reading.fetch_sub(1, Relaxed);
+
+        let t = t?;
+
+        if let Some(last_read) = lock(&self.readers)?.get_mut(&reader) {
+            *last_read = Instant::now();
+        }
+
+        Ok(t)
     }
 
-    pub fn new_arc() -> Arc<Self> { Arc::new(Self::new()) }
+    /// Registered readers that haven't read a message within `threshold`,
+    /// counting from when they registered if they have never read one
+    pub fn starved_readers(&self, threshold: Duration) -> Result<Vec<ReaderID>, MsgQueueError> {
+        Ok(lock(&self.readers)?
+            .iter()
+            .filter(|(_, last_read)| last_read.elapsed() > threshold)
+            .map(|(&id, _)| id)
+            .collect())
+    }
 
-    pub fn is_closed(&self) -> Result<bool, MsgQueueError> {
-        self.can_send().map(|v| !v)
+    /// Requires writers to call `heartbeat` at least once every `timeout`
+    /// 
+    /// Writers that miss their heartbeat are deregistered by the reaper thread
+    /// started with `spawn_writer_reaper`, which closes the queue if it was the last writer
+    pub fn with_writer_timeout(mut self, timeout: Duration) -> Self {
+        self.writer_timeout = Some(timeout);
+        self
     }
 
-    pub fn is_terminated(&self) -> Result<bool, MsgQueueError> {
-        self.can_read().map(|v| !v)
+    fn new_writer_id(&self) -> WriterID {
+        rand::thread_rng().gen()
     }
 
-    pub fn can_send(&self) -> Result<bool, MsgQueueError> {
-        Ok(self.state.lock().map_err(|_| NoLock)?.can_send())
+    fn check_writer(&self, id: WriterID) -> Result<(), MsgQueueError> {
+        if self.is_writer(id)? { Ok(()) } else { Err(UnknownWriter) }
     }
 
-    pub fn can_read(&self) -> Result<bool, MsgQueueError> {
-        Ok(self.state.lock().map_err(|_| NoLock)?.can_read())
+    /// Whether `id` is a currently registered writer
+    pub fn is_writer(&self, id: WriterID) -> Result<bool, MsgQueueError> {
+        Ok(self.lock_writers()?.contains(&id))
     }
 
-    fn terminate(&self) -> Result<(), MsgQueueError> {
-        Ok(self.state.lock().map_err(|_| NoLock)?.terminate())
+    /// The number of currently registered writers, as opposed to the number of unread messages
+    pub fn num_writers(&self) -> Result<usize, MsgQueueError> {
+        Ok(self.lock_writers()?.len())
     }
 
-    /// Prevent any writers from sending any more messages
-    fn close(&self) -> Result<(), MsgQueueError> {
-        if self.is_closed()? { return Err(QueueClosed) }
+    pub fn register_writer(&self) -> Result<WriterID, MsgQueueError> {
+        let id = self.new_writer_id();
+
+        self.register_writer_as(id)?;
 
-        self.state
-            .lock().map_err(|_| NoLock)?
-            .close();
+        Ok(id)
+    }
 
-        Ok(())
+    /// Registers a writer, returning a guard that deregisters it when dropped
+    pub fn register_writer_guard(&self) -> Result<WriterGuard<'_, T>, MsgQueueError> {
+        Ok(WriterGuard::new(self, self.register_writer()?))
     }
 
-    /// Enqueues a message
-    pub fn send(&self, id: WriterID, t: T) -> Result<(), MsgQueueError> {
-        self.check_writer(id)?;
+    /// Registers a writer, returning a guard that shares ownership of the queue
+    /// and deregisters the writer when dropped
+    pub fn register_writer_guard_arc(self: &Arc<Self>) -> Result<WriterGuard<'static, T>, MsgQueueError> {
+        Ok(WriterGuard::new_shared(self.clone(), self.register_writer()?))
+    }
 
-        if !self.can_send()? { return Err(QueueClosed) }
+    /// Registers a writer with an id chosen by the caller
+    fn register_writer_as(&self, id: WriterID) -> Result<(), MsgQueueError> {
+        self.lock_writers()?.push(id);
 
-        self.queue
-            .lock().map_err(|_| NoLock)?
-            .push(t);
+        if self.writer_timeout.is_some() {
+            lock(&self.heartbeats)?
+                .insert(id, Instant::now());
+        }
 
         Ok(())
     }
 
-    fn pop(&self) -> Result<T, MsgQueueError> {
-        if self.is_terminated()? { return Err(QueueTerminated) }
+    /// Undoes `register_writer_as`, without closing the queue if it was the last writer
+    pub(crate) fn unregister_writer_as(&self, id: WriterID) -> Result<(), MsgQueueError> {
+        self.lock_writers()?.retain(|&writer| writer != id);
 
-        let mut lock = self.queue
-            .lock().map_err(|_| NoLock)?;
+        lock(&self.heartbeats)?
+            .remove(&id);
 
-        match lock.pop() {
-            Some(v) => Ok(v),
-            None => if self.is_closed()? {
-                self.terminate()?;
-                Err(EndOfTransmission)
-            } else {
-                Err(NoMessages)
-            },
+        Ok(())
+    }
+
+    /// Records that a writer is still alive
+    pub fn heartbeat(&self, id: WriterID) -> Result<(), MsgQueueError> {
+        self.check_writer(id)?;
+
+        if self.writer_timeout.is_some() {
+            lock(&self.heartbeats)?
+                .insert(id, Instant::now());
         }
+
+        Ok(())
     }
 
-    /// Reads the next message from the queue
-    /// 
-    /// If there are no messages, this function will busy wait for one
-    pub fn read(&self) -> Result<T, MsgQueueError> {
-        loop {
-            match self.pop() {
-                Err(NoMessages) => continue,
-                Ok(v) => return Ok(v),
+    /// Deregisters every writer that hasn't sent a heartbeat within the writer timeout
+    fn reap_writers(&self) -> Result<(), MsgQueueError> {
+        let Some(timeout) = self.writer_timeout else { return Ok(()) };
+
+        let expired: Vec<WriterID> = lock(&self.heartbeats)?
+            .iter()
+            .filter(|(_, &last)| last.elapsed() > timeout)
+            .map(|(&id, _)| id)
+            .collect();
+
+        for id in expired {
+            match self.deregister_writer(id) {
+                Ok(()) | Err(UnknownWriter) => {},
                 Err(e) => return Err(e),
             }
         }
+
+        Ok(())
+    }
+
+    /// Starts a thread that periodically deregisters writers that missed their heartbeat
+    /// 
+    /// The thread checks twice per writer timeout, and stops once the queue is closed or dropped
+    pub fn spawn_writer_reaper(self: &Arc<Self>) -> std::thread::JoinHandle<Result<(), MsgQueueError>>
+    where T: Send + 'static {
+        let queue = Arc::downgrade(self);
+        let interval = self.writer_timeout.unwrap_or_default() / 2;
+
+        std::thread::spawn(move || {
+            loop {
+                std::thread::sleep(interval);
+
+                let Some(queue) = queue.upgrade() else { return Ok(()) };
+
+                if queue.is_closed()? || queue.writer_timeout.is_none() { return Ok(()) }
+
+                queue.reap_writers()?;
+            }
+        })
+    }
+
+    pub fn deregister_writer(&self, id: WriterID) -> Result<(), MsgQueueError> {
+        self.deregister_writer_reporting(id).map(|_| ())
+    }
+
+    /// Deregisters a writer, returning whether doing so closed the queue
+    pub fn deregister_writer_reporting(&self, id: WriterID) -> Result<bool, MsgQueueError> {
+        let mut writers = self.lock_writers()?;
+
+        let index = writers.iter()
+            .position(|&writer| writer == id)
+            .ok_or(UnknownWriter)?;
+
+        writers.remove(index);
+
+        lock(&self.heartbeats)?
+            .remove(&id);
+
+        if writers.is_empty() { return self.close(CloseReason::LastWriterDeregistered) }
+
+        Ok(false)
+    }
+
+    /// Registers a writer for the duration of `f`, returning its result
+    /// 
+    /// The writer is deregistered before this returns, even if `f` panics
+    pub fn with_writer<R>(&self, f: impl FnOnce(WriterID) -> R) -> Result<R, MsgQueueError> {
+        struct Registration<'a, T> {
+            queue: &'a AsyncMsgQueue<T>,
+            id: Option<WriterID>,
+        }
+
+        impl<T> Drop for Registration<'_, T> {
+            fn drop(&mut self) {
+                if let Some(id) = self.id.take() {
+                    let _ = self.queue.deregister_writer(id);
+                }
+            }
+        }
+
+        let id = self.register_writer()?;
+        let mut registration = Registration { queue: self, id: Some(id) };
+
+        let result = f(id);
+
+        registration.id = None;
+        self.deregister_writer(id)?;
+
+        Ok(result)
+    }
+
+    pub fn new_arc() -> Arc<Self> { Arc::new(Self::new()) }
+
+    /// A queue identified by `name` in logs
+    pub fn new_named(name: impl Into<String>) -> Self { Self { name: name.into(), ..Self::new() } }
+
+    pub fn new_named_arc(name: impl Into<String>) -> Arc<Self> { Arc::new(Self::new_named(name)) }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// A queue holding at most `capacity` unread messages, past which `send` returns `QueueFull`
+    pub fn new_bounded(capacity: usize) -> Self { Self::new().with_capacity(capacity) }
+
+    pub fn new_bounded_arc(capacity: usize) -> Arc<Self> { Arc::new(Self::new_bounded(capacity)) }
+
+    #[cfg(not(feature = "metrics"))]
+    fn lock_queue(&self) -> Result<MutexGuard<'_, Queue<T>>, MsgQueueError> {
+        lock(&self.queue)
+    }
+
+    /// Tries the lock first, so that blocking on it can be counted
+    #[cfg(feature = "metrics")]
+    fn lock_queue(&self) -> Result<MutexGuard<'_, Queue<T>>, MsgQueueError> {
+        match self.queue.try_lock() {
+            Ok(lock) => Ok(lock),
+            Err(std::sync::TryLockError::WouldBlock) => {
+                self.lock_contention.fetch_add(1, Relaxed);
+                lock(&self.queue)
+            },
+            Err(std::sync::TryLockError::Poisoned(_)) => Err(NoLock),
+        }
+    }
+
+    fn lock_state(&self) -> Result<MutexGuard<'_, QueueState>, MsgQueueError> {
+        lock(&self.state)
+    }
+
+    fn lock_writers(&self) -> Result<MutexGuard<'_, Vec<WriterID>>, MsgQueueError> {
+        lock(&self.writers)
+    }
+
+    pub fn is_closed(&self) -> Result<bool, MsgQueueError> {
+        self.can_send().map(|v| !v)
+    }
+
+    pub fn is_terminated(&self) -> Result<bool, MsgQueueError> {
+        self.can_read().map(|v| !v)
+    }
+
+    pub fn can_send(&self) -> Result<bool, MsgQueueError> {
+        Ok(self.lock_state()?.can_send())
+    }
+
+    pub fn can_read(&self) -> Result<bool, MsgQueueError> {
+        Ok(self.lock_state()?.can_read())
+    }
+
+    /// Why the queue closed, or `None` if it is still open
+    /// 
+    /// A writer that gets `QueueClosed` can use this to tell whether another writer
+    /// closed the queue by deregistering, or a reader closed it by reading a poison pill.
+    /// A queue that closed before it was terminated keeps the reason it closed for.
+    pub fn close_reason(&self) -> Result<Option<CloseReason>, MsgQueueError> {
+        Ok(*lock(&self.close_reason)?)
+    }
+
+    /// The queue's state, or `None` if the lock is held by another thread
+    pub fn try_state(&self) -> Option<QueueState> {
+        self.state.try_lock().ok().map(|state| *state)
+    }
+
+    /// The number of unread messages
+    pub fn len(&self) -> Result<usize, MsgQueueError> {
+        Ok(self.lock_queue()?.len())
+    }
+
+    /// Whether there are no unread messages
+    pub fn is_empty(&self) -> Result<bool, MsgQueueError> {
+        Ok(self.len()? == 0)
+    }
+
+    /// The number of unread messages, or `None` if the lock is held by another thread
+    pub fn try_len(&self) -> Option<usize> {
+        self.queue.try_lock().ok().map(|queue| queue.len())
+    }
+
+    /// Must be called while holding the `queue` lock, so parked readers can't miss the wake up
+    fn terminate(&self) -> Result<(), MsgQueueError> {
+        let mut state = self.lock_state()?;
+        let terminating = state.can_read();
+
+        if state.can_send() { *lock(&self.close_reason)? = Some(CloseReason::Terminated) }
+
+        state.terminate();
+
+        #[cfg(feature = "async")]
+        self.wake_state_futures()?;
+
+        drop(state);
+
+        if terminating { self.report(|metrics| metrics.on_terminate()) }
+
+        #[cfg(feature = "async")]
+        self.wake_read_futures();
+
+        self.readable.notify_all();
+        self.writable.notify_all();
+
+        Ok(())
+    }
+
+    /// Terminate the queue immediately, discarding any unread messages
+    /// 
+    /// Readers waiting for a message are woken and get `QueueTerminated`,
+    /// as does every read and send after this, regardless of any registered writers
+    pub fn force_terminate(&self) -> Result<(), MsgQueueError> {
+        let mut queue = self.lock_queue()?;
+
+        let oldest = queue.deque.iter().map(|slot| slot.order).min();
+        queue.abandoned = queue.abandoned.or(oldest);
+        queue.reset();
+
+        self.terminate()
+    }
+
+    /// Discards every unread message and starts a new epoch, without changing the queue's state
+    pub fn clear(&self) -> Result<(), MsgQueueError> {
+        self.lock_queue()?.reset();
+
+        self.writable.notify_all();
+
+        Ok(())
+    }
+
+    /// Reserves buffer space for at least `additional` more messages
+    /// 
+    /// This only affects allocation, so a bounded queue still can't hold more than its capacity
+    pub fn reserve(&self, additional: usize) -> Result<(), MsgQueueError> {
+        self.lock_queue()?.deque.reserve(additional);
+        Ok(())
+    }
+
+    /// Returns excess buffer memory to the allocator, without changing the queue's contents
+    /// 
+    /// Useful after a burst of messages leaves a long-lived queue with a large allocation
+    pub fn shrink_to_fit(&self) -> Result<(), MsgQueueError> {
+        self.lock_queue()?.deque.shrink_to_fit();
+        Ok(())
+    }
+
+    /// The number of bytes allocated for buffered messages, whether or not they are in use
+    /// 
+    /// This only counts the buffer itself, not any memory the messages own
+    pub fn allocated_bytes(&self) -> Result<usize, MsgQueueError> {
+        Ok(self.lock_queue()?.deque.capacity() * std::mem::size_of::<Slot<T>>())
+    }
+
+    /// The current epoch, which starts at 0 and increases each time the queue is cleared or reopened
+    pub fn epoch(&self) -> Result<u64, MsgQueueError> {
+        Ok(self.lock_queue()?.epoch)
+    }
+
+    /// Terminates the queue at `when`, regardless of any registered writers
+    /// 
+    /// Any messages still buffered at the deadline are discarded.
+    /// Calling this again reschedules the deadline. A single timer thread waits for
+    /// the latest deadline, and stops early if the queue is dropped.
+    pub fn terminate_at(self: &Arc<Self>, when: Instant) -> Result<(), MsgQueueError>
+    where T: Send + 'static {
+        if !self.deadline.0.set(Some(when))? { return Ok(()) }
+
+        let deadline = self.deadline.0.clone();
+        let queue = Arc::downgrade(self);
+
+        std::thread::spawn(move || {
+            if !deadline.wait()? { return Ok(()) }
+
+            match queue.upgrade() {
+                Some(queue) => queue.force_terminate(),
+                None => Ok(()),
+            }
+        });
+
+        Ok(())
+    }
+
+    /// Prevent any writers from sending any more messages, returning whether this closed the queue
+    /// 
+    /// Closing a queue that is already closed or terminated does nothing
+    fn close(&self, reason: CloseReason) -> Result<bool, MsgQueueError> {
+        let _lock = self.lock_queue()?;
+
+        if self.is_closed()? { return Ok(false) }
+
+        self.close_locked(reason)?;
+
+        Ok(true)
+    }
+
+    /// Lets writers send to a closed queue again, starting a new epoch
+    /// 
+    /// Messages sent before the queue closed keep their epoch, so readers can use
+    /// `read_with_epoch` to tell them apart from those sent after it reopened.
+    /// A closed queue terminates once a reader finds it empty, after which it can't be reopened.
+    /// Reopening an open queue does nothing.
+    pub fn reopen(&self) -> Result<(), MsgQueueError> {
+        let mut queue = self.lock_queue()?;
+        let mut state = self.lock_state()?;
+
+        if !state.can_read() { return Err(QueueTerminated) }
+        if state.can_send() { return Ok(()) }
+
+        state.reopen();
+        queue.epoch += 1;
+        *lock(&self.close_reason)? = None;
+
+        Ok(())
+    }
+
+    /// Must be called while holding the `queue` lock, so parked readers can't miss the wake up
+    fn close_locked(&self, reason: CloseReason) -> Result<(), MsgQueueError> {
+        let mut state = self.lock_state()?;
+        let closing = state.can_send();
+
+        if closing {
+            state.close();
+            *lock(&self.close_reason)? = Some(reason);
+        }
+
+        #[cfg(feature = "async")]
+        self.wake_state_futures()?;
+
+        drop(state);
+
+        if closing { self.report(|metrics| metrics.on_close(reason)) }
+
+        #[cfg(feature = "async")]
+        self.wake_read_futures();
+
+        self.readable.notify_all();
+        self.writable.notify_all();
+
+        Ok(())
+    }
+
+    /// Resolves once the queue is closed, or immediately if it already is
+    #[cfg(feature = "async")]
+    pub fn closed(&self) -> StateFuture<'_, T> {
+        StateFuture::new(self, |state| !state.can_send())
+    }
+
+    /// Resolves once the queue is terminated, or immediately if it already is
+    #[cfg(feature = "async")]
+    pub fn terminated(&self) -> StateFuture<'_, T> {
+        StateFuture::new(self, |state| !state.can_read())
+    }
+
+    /// Must be called while holding the `state` lock, so a polling future can't miss the change
+    #[cfg(feature = "async")]
+    fn wake_state_futures(&self) -> Result<(), MsgQueueError> {
+        lock(&self.state_wakers)?
+            .drain(..)
+            .for_each(std::task::Waker::wake);
+
+        Ok(())
+    }
+
+    /// Must be called while holding the `queue` lock, so a polling stream can't miss the message
+    /// 
+    /// A poisoned lock is ignored, since streams stop polling once the queue's locks are poisoned
+    #[cfg(feature = "async")]
+    fn wake_read_futures(&self) {
+        if let Ok(mut wakers) = lock(&self.read_wakers) {
+            wakers.drain(..).for_each(std::task::Waker::wake);
+        }
+    }
+
+    /// A stream of batches of up to `chunk` messages, see `ChunkStream`
+    #[cfg(feature = "async")]
+    pub fn into_chunked_stream(self: Arc<Self>, chunk: usize) -> ChunkStream<T> {
+        ChunkStream::new(self, chunk)
+    }
+
+    /// Removes every unread message matching `pred` and returns them in the order they would have been read
+    /// 
+    /// The remaining messages keep their order. The queue's state and writers are unaffected.
+    pub fn extract<F: FnMut(&T) -> bool>(&self, pred: F) -> Result<Vec<T>, MsgQueueError> {
+        let extracted = self.lock_queue()?.extract(pred);
+
+        self.writable.notify_all();
+
+        Ok(extracted)
+    }
+
+    /// Consumes the queue, returning every unread message in the order they would be read
+    pub fn into_vec(self) -> Vec<T> {
+        self.queue
+            .into_inner().unwrap_or_else(|e| e.into_inner())
+            .into_vec()
+    }
+
+    /// The number of messages ever added to the queue
+    pub fn sent_count(&self) -> u64 {
+        self.sent.load(Relaxed)
+    }
+
+    /// The number of messages ever read from the queue
+    /// 
+    /// `sent_count() - read_count()` is the number of messages buffered or discarded
+    pub fn read_count(&self) -> u64 {
+        self.read.load(Relaxed)
+    }
+
+    /// The number of messages dropped by the `DropOldest` or `DropNewest` overflow policies
+    pub fn dropped_count(&self) -> u64 {
+        self.dropped.load(Relaxed)
+    }
+
+    /// The number of times a thread had to wait for another to release the queue lock
+    #[cfg(feature = "metrics")]
+    pub fn lock_contention_count(&self) -> u64 {
+        self.lock_contention.load(Relaxed)
+    }
+
+    /// Checks that the writer may send and the validator accepts `t`,
+    /// then hands the locked buffer to `f` along with `t`
+    fn send_with<R, F: FnOnce(&mut Queue<T>, T) -> R>(&self, id: WriterID, t: T, f: F) -> Result<R, MsgQueueError> {
+        self.validate(id, &t)?;
+
+        let mut lock = self.lock_for_send(id)?;
+
+        #[cfg(feature = "testing")]
+        self.run_send_barrier()?;
+
+        let len = lock.len();
+        let result = f(&mut lock, t);
+
+        self.sent.fetch_add(lock.len().saturating_sub(len) as u64, Relaxed);
+        self.notify_readable();
+
+        Ok(result)
+    }
+
+    /// Wakes parked readers after a send, according to the queue's `WakeStrategy`
+    fn notify_readable(&self) {
+        #[cfg(feature = "async")]
+        self.wake_read_futures();
+
+        match self.wake_strategy {
+            WakeStrategy::One => self.readable.notify_one(),
+            WakeStrategy::All => self.readable.notify_all(),
+        }
+    }
+
+    /// Checks that the writer may send, then locks the buffer
+    /// 
+    /// The writers lock is held until the buffer is locked, and the state is checked afterwards,
+    /// so a writer deregistering and closing the queue can't slip in before the message is pushed
+    fn lock_for_send(&self, id: WriterID) -> Result<MutexGuard<'_, Queue<T>>, MsgQueueError> {
+        let writers = self.lock_writers()?;
+
+        if !writers.contains(&id) { return Err(UnknownWriter) }
+
+        let lock = self.lock_queue()?;
+
+        drop(writers);
+
+        if !self.can_send()? { return Err(QueueClosed) }
+
+        Ok(lock)
+    }
+
+    /// Sets a hook that every send calls after checking the queue can be sent to,
+    /// just before pushing its message
+    /// 
+    /// The hook can block to pause a sender at that point, so tests can deterministically
+    /// reproduce races with closing or terminating the queue. It runs while holding the queue
+    /// lock, so it mustn't use the queue, and anything else needing the lock waits for it.
+    #[cfg(feature = "testing")]
+    pub fn set_send_barrier(&self, hook: impl Fn() + Send + Sync + 'static) -> Result<(), MsgQueueError> {
+        *lock(&self.send_barrier)? = Some(Box::new(hook));
+        Ok(())
+    }
+
+    #[cfg(feature = "testing")]
+    fn run_send_barrier(&self) -> Result<(), MsgQueueError> {
+        if let Some(hook) = lock(&self.send_barrier)?.as_ref() { hook() }
+        Ok(())
+    }
+
+    /// Runs the send validator, if there is one, outside of any lock
+    /// 
+    /// The writer and state are checked first, so only messages that could be sent are validated.
+    /// `lock_for_send` checks them again, as either could change once the locks are released.
+    fn validate(&self, id: WriterID, t: &T) -> Result<(), MsgQueueError> {
+        let Some(validator) = &self.send_validator else { return Ok(()) };
+
+        if !self.is_writer(id)? { return Err(UnknownWriter) }
+
+        if !self.can_send()? { return Err(QueueClosed) }
+
+        if !validator(t) { return Err(Rejected) }
+
+        Ok(())
+    }
+
+    /// Enqueues a message with `push`, first making room for it according to the overflow policy
+    /// 
+    /// Returns `None` if the message was dropped to respect the policy,
+    /// and hands the message back along with the error if it can't be sent
+    fn send_or_return<R, F: FnOnce(&mut Queue<T>, T) -> R>(
+        &self,
+        id: WriterID,
+        t: T,
+        wait: SendWait,
+        push: F,
+    ) -> Result<Option<R>, (MsgQueueError, T)> {
+        self.send_under_policy(id, t, wait, self.overflow_policy, push)
+    }
+
+    /// Like `send_or_return`, but following `policy` in place of the queue's overflow policy
+    fn send_under_policy<R, F: FnOnce(&mut Queue<T>, T) -> R>(
+        &self,
+        id: WriterID,
+        t: T,
+        wait: SendWait,
+        policy: OverflowPolicy,
+        push: F,
+    ) -> Result<Option<R>, (MsgQueueError, T)> {
+        if let Err(e) = self.validate(id, &t) { return Err((e, t)) }
+
+        let lock = match self.lock_for_send(id) {
+            Ok(lock) => lock,
+            Err(e) => return Err((e, t)),
+        };
+
+        let mut lock = match self.make_room(lock, wait, policy) {
+            Ok(Some(lock)) => lock,
+            Ok(None) => return Ok(None),
+            Err(e) => return Err((e, t)),
+        };
+
+        #[cfg(feature = "testing")]
+        if let Err(e) = self.run_send_barrier() { return Err((e, t)) }
+
+        let result = push(&mut lock, t);
+
+        self.sent.fetch_add(1, Relaxed);
+        self.notify_readable();
+
+        drop(lock);
+        self.report(|metrics| metrics.on_send());
+
+        Ok(Some(result))
+    }
+
+    /// Applies `policy` if the queue is full,
+    /// returning `None` if the new message should be dropped
+    /// 
+    /// The `Block` policy waits according to `wait`, and returns `QueueFull` if it can't wait
+    /// or `Timeout` if the wait runs out
+    fn make_room<'a>(
+        &self,
+        mut lock: MutexGuard<'a, Queue<T>>,
+        wait: SendWait,
+        policy: OverflowPolicy,
+    ) -> Result<Option<MutexGuard<'a, Queue<T>>>, MsgQueueError> {
+        let Some(capacity) = self.capacity else { return Ok(Some(lock)) };
+
+        let waiting_since = Instant::now();
+        let mut waited = false;
+        let mut hit = false;
+
+        while lock.len() >= self.capacity_limit(capacity) {
+            // However long it waits, a send only counts once towards growing the target
+            if !std::mem::replace(&mut hit, true) && self.grow_capacity_target(capacity) { continue }
+
+            match (policy, wait) {
+                (OverflowPolicy::Block, SendWait::Forever) => {
+                    waited = true;
+                    lock = self.writable.wait(lock).map_err(|_| NoLock)?;
+
+                    if !self.can_send()? { return Err(QueueClosed) }
+                },
+                (OverflowPolicy::Block, SendWait::Until(deadline)) => {
+                    let now = Instant::now();
+
+                    if now >= deadline { return Err(Timeout) }
+
+                    waited = true;
+                    lock = self.writable.wait_timeout(lock, deadline - now).map_err(|_| NoLock)?.0;
+
+                    if !self.can_send()? { return Err(QueueClosed) }
+                },
+                (OverflowPolicy::Block, SendWait::Never) |
+                (OverflowPolicy::Error, _) => return Err(QueueFull),
+                (OverflowPolicy::DropOldest, _) => {
+                    lock.pop_oldest();
+                    self.dropped.fetch_add(1, Relaxed);
+                },
+                (OverflowPolicy::DropNewest, _) => {
+                    self.dropped.fetch_add(1, Relaxed);
+                    return Ok(None)
+                },
+            }
+        }
+
+        if !hit { self.capacity_hits.store(0, Relaxed) }
+
+        if let Some(hook) = self.backpressure_hook.as_ref().filter(|_| waited) {
+            hook(waiting_since.elapsed())
+        }
+
+        Ok(Some(lock))
+    }
+
+    /// The number of messages past which a send must make room
+    fn capacity_limit(&self, capacity: usize) -> usize {
+        self.capacity_target().unwrap_or(capacity)
+    }
+
+    /// Counts a send that found an adaptive queue at its target, and once `CAPACITY_GROWTH_HITS`
+    /// have in a row, doubles the target, up to `capacity`, returning whether it grew
+    /// 
+    /// Must be called while holding the queue lock
+    fn grow_capacity_target(&self, capacity: usize) -> bool {
+        let Some(target) = self.capacity_target() else { return false };
+
+        if target >= capacity { return false }
+
+        if self.capacity_hits.fetch_add(1, Relaxed) + 1 < CAPACITY_GROWTH_HITS { return false }
+
+        self.capacity_hits.store(0, Relaxed);
+
+        self.capacity_target.store((target * 2).clamp(1, capacity), Relaxed);
+
+        true
+    }
+
+    /// Halves an adaptive queue's target once a read has emptied it, and shrinks the buffer
+    fn shrink_capacity_target(&self, lock: &mut MutexGuard<Queue<T>>) {
+        let Some((min, _)) = self.adaptive_capacity else { return };
+
+        if lock.len() > 0 { return }
+
+        let target = (self.capacity_target.load(Relaxed) / 2).max(min);
+
+        self.capacity_target.store(target, Relaxed);
+        lock.deque.shrink_to(target);
+    }
+
+    /// Enqueues a message
+    /// 
+    /// If the queue is full, this follows the queue's `OverflowPolicy`
+    /// 
+    /// A message accepted with `Ok` is always read before the queue terminates, even if the
+    /// last writer deregisters at the same moment: the push and the close both happen under
+    /// the queue lock, and the queue only terminates once a reader finds it closed and empty.
+    /// The only exceptions are messages deliberately discarded, by `clear`, `extract`,
+    /// the `DropOldest` policy, or terminating the queue early with `terminate_at`.
+    pub fn send(&self, id: WriterID, t: T) -> Result<(), MsgQueueError> {
+        self.send_or_return(id, t, SendWait::Forever, Queue::push)
+            .map(|_| ())
+            .map_err(|(e, _)| e)
+    }
+
+    /// Enqueues a message like `send`, but hands it back along with the error if it can't be sent
+    /// 
+    /// This lets a writer retry or reroute a message that was rejected by the send validator,
+    /// or that found the queue closed or full, without having to clone it first
+    pub fn send_returning(&self, id: WriterID, t: T) -> Result<(), (MsgQueueError, T)> {
+        self.send_or_return(id, t, SendWait::Forever, Queue::push)
+            .map(|_| ())
+    }
+
+    /// Enqueues a message, waiting up to `timeout` for space if the queue is full, whatever its overflow policy
+    /// 
+    /// If there is still no space, this returns `Timeout` along with the message, so it isn't lost.
+    /// If the queue closes while waiting, this returns `QueueClosed` straight away.
+    pub fn send_timeout(&self, id: WriterID, t: T, timeout: Duration) -> Result<(), (MsgQueueError, T)> {
+        let wait = SendWait::Until(Instant::now() + timeout);
+
+        self.send_under_policy(id, t, wait, OverflowPolicy::Block, Queue::push)
+            .map(|_| ())
+    }
+
+    /// Enqueues a message along with key/value headers, which `read_with_headers` returns
+    /// 
+    /// Plain `read` discards the headers
+    pub fn send_with_headers(&self, id: WriterID, t: T, headers: Headers) -> Result<(), MsgQueueError> {
+        self.send_or_return(id, t, SendWait::Forever, |queue, t| queue.push_with_headers(t, headers))
+            .map(|_| ())
+            .map_err(|(e, _)| e)
+    }
+
+    /// Enqueues a message, waiting for space if the queue is full, whatever its overflow policy
+    /// 
+    /// Returns `QueueClosed` if the queue closes while waiting
+    pub fn send_blocking(&self, id: WriterID, t: T) -> Result<(), MsgQueueError> {
+        self.send_under_policy(id, t, SendWait::Forever, OverflowPolicy::Block, Queue::push)
+            .map(|_| ())
+            .map_err(|(e, _)| e)
+    }
+
+    /// Enqueues a message without waiting for space
+    /// 
+    /// This behaves like `send`, except that a full queue with the `Block` policy returns `QueueFull`
+    pub fn try_send(&self, id: WriterID, t: T) -> Result<(), MsgQueueError> {
+        self.send_or_return(id, t, SendWait::Never, Queue::push)
+            .map(|_| ())
+            .map_err(|(e, _)| e)
+    }
+
+    /// Enqueues a message only if `cond` holds for the buffered messages, returning whether it was sent
+    /// 
+    /// `cond` iterates over the buffered messages in the order they would be read, and runs under
+    /// the queue lock, so nothing can be sent or read between the check and the send.
+    /// It gets an iterator rather than a slice because messages are buffered alongside their
+    /// headers, and `send_front` and `ReadOrder::Lifo` change the order they're read in,
+    /// so a slice would mean copying the buffer on every call.
+    /// 
+    /// Waiting for space would release the lock, so a full queue with the `Block` policy
+    /// returns `QueueFull` instead, and the `DropNewest` policy returns `Ok(false)`.
+    /// As with `send`, the send validator checks the message first, before `cond` is called.
+    pub fn send_if<F>(&self, id: WriterID, t: T, cond: F) -> Result<bool, MsgQueueError>
+    where F: FnOnce(&mut dyn Iterator<Item = &T>) -> bool {
+        self.validate(id, &t)?;
+
+        let lock = self.lock_for_send(id)?;
+
+        if !cond(&mut lock.iter()) { return Ok(false) }
+
+        let Some(mut lock) = self.make_room(lock, SendWait::Never, self.overflow_policy)? else { return Ok(false) };
+
+        #[cfg(feature = "testing")]
+        self.run_send_barrier()?;
+
+        lock.push(t);
+
+        self.sent.fetch_add(1, Relaxed);
+        self.notify_readable();
+
+        drop(lock);
+        self.report(|metrics| metrics.on_send());
+
+        Ok(true)
+    }
+
+    /// Enqueues a message, returning the sequence number assigned to it
+    /// 
+    /// Sequence numbers count up from zero in the order messages are sent,
+    /// not counting those sent with `send_front`. They increase monotonically for the life of
+    /// the queue, and aren't reset when a new epoch starts, so `seq_gap` across a `clear`
+    /// counts exactly the messages it discarded rather than a spurious jump.
+    /// If the `DropNewest` policy drops the message, no number is assigned and this returns `QueueFull`
+    pub fn send_seq(&self, id: WriterID, t: T) -> Result<u64, MsgQueueError> {
+        let seq = self.send_or_return(id, t, SendWait::Forever, |queue, t| {
+                let seq = queue.pushed;
+                queue.push(t);
+                seq
+            })
+            .map_err(|(e, _)| e)?;
+
+        seq.ok_or(QueueFull)
+    }
+
+    /// Enqueues a message, returning whether a reader was parked waiting for it
+    /// 
+    /// Only readers parked on the condvar count, not spinning readers
+    pub fn send_handoff(&self, id: WriterID, t: T) -> Result<bool, MsgQueueError> {
+        self.send_or_return(id, t, SendWait::Forever, |queue, t| {
+                queue.push(t);
+                self.parked_readers.load(Relaxed) > 0
+            })
+            .map(|handed_off| handed_off.unwrap_or(false))
+            .map_err(|(e, _)| e)
+    }
+
+    /// Marks the current end of the queue
+    /// 
+    /// The barrier is reached once every message sent before it has been removed from the
+    /// queue, and since messages are read in order, no message sent after it can be read first.
+    /// Barriers don't take up a slot in the queue and are never returned by `read`.
+    /// Any reader can check `reached_barrier` independently, regardless of which
+    /// readers read the messages before it.
+    pub fn barrier(&self, id: WriterID) -> Result<BarrierToken, MsgQueueError> {
+        Ok(BarrierToken(self.lock_for_send(id)?.pushed))
+    }
+
+    /// Whether every message sent before the barrier has been read or discarded
+    pub fn reached_barrier(&self, token: BarrierToken) -> Result<bool, MsgQueueError> {
+        Ok(self.lock_queue()?.removed >= token.0)
+    }
+
+    /// Blocks until every buffered message has been read or discarded
+    /// 
+    /// The queue stays open afterwards. Returns `QueueTerminated` if the queue is terminated
+    /// with messages that were buffered when `flush` was called still undelivered.
+    pub fn flush(&self) -> Result<(), MsgQueueError> {
+        let mut lock = self.lock_queue()?;
+
+        let newest = lock.inserted;
+        let abandoned = lock.abandoned;
+
+        while lock.len() > 0 {
+            lock = self.writable.wait(lock).map_err(|_| NoLock)?;
+        }
+
+        // The queue may have been reopened since, and later messages abandoned,
+        // so it's only an error if one of these was still buffered when it was force terminated
+        let lost = abandoned.is_none() && lock.abandoned.is_some_and(|oldest| oldest <= newest);
+        if lost { return Err(QueueTerminated) }
+
+        Ok(())
+    }
+
+    /// Blocks until the queue is closed, whether by its last writer deregistering or explicitly
+    /// 
+    /// This is the signal that every producer is done, returning immediately if the queue is
+    /// already closed or terminated. Unlike waiting for termination, messages may still be unread.
+    /// It waits on the same condvar as blocked sends, which is notified whenever the queue closes.
+    pub fn await_close(&self) -> Result<(), MsgQueueError> {
+        let mut lock = self.lock_queue()?;
+
+        while self.can_send()? {
+            lock = self.writable.wait(lock).map_err(|_| NoLock)?;
+        }
+
+        Ok(())
+    }
+
+    /// Enqueues a message at the front of the queue, so that it is the next message read
+    /// 
+    /// This deliberately breaks FIFO ordering: messages sent this way are read
+    /// before every other message, most recent first, and they skip any barriers
+    pub fn send_front(&self, id: WriterID, t: T) -> Result<(), MsgQueueError> {
+        self.send_or_return(id, t, SendWait::Forever, Queue::push_front)
+            .map(|_| ())
+            .map_err(|(e, _)| e)
+    }
+
+    fn pop<R, F: FnMut(&mut Queue<T>) -> Option<R>>(&self, f: F) -> Result<R, MsgQueueError> {
+        let mut lock = self.lock_queue()?;
+
+        self.pop_locked(&mut lock, f)
+    }
+
+    /// Takes from the locked buffer with `f`, which returns `None` when there is nothing to take
+    fn pop_locked<R, F: FnMut(&mut Queue<T>) -> Option<R>>(
+        &self,
+        lock: &mut MutexGuard<Queue<T>>,
+        mut f: F,
+    ) -> Result<R, MsgQueueError> {
+        if self.is_terminated()? { return Err(QueueTerminated) }
+
+        let len = lock.len();
+        let result = f(lock);
+
+        if std::mem::take(&mut lock.poisoned) { self.close_locked(CloseReason::PoisonPill)? }
+
+        match result {
+            Some(v) => {
+                self.read.fetch_add(len.saturating_sub(lock.len()) as u64, Relaxed);
+                self.shrink_capacity_target(lock);
+                self.writable.notify_all();
+                Ok(v)
+            },
+            None => if self.is_closed()? && self.readers_allow_termination()? {
+                self.terminate()?;
+                Err(EndOfTransmission)
+            } else {
+                Err(NoMessages)
+            },
+        }
+    }
+
+    /// Waits on the `readable` condvar until a message arrives or the queue ends
+    fn read_parked<R, F: FnMut(&mut Queue<T>) -> Option<R>>(&self, mut f: F) -> Result<R, MsgQueueError> {
+        let mut lock = self.lock_queue()?;
+
+        loop {
+            match self.pop_locked(&mut lock, &mut f) {
+                Err(NoMessages) => lock = self.park(lock)?,
+                Ok(v) => return Ok(v),
+                Err(e) => return Err(e),
+            }
+        }
+    }
+
+    /// Parks on the `readable` condvar until a writer, or a change of state, wakes the reader
+    fn park<'a>(&self, lock: MutexGuard<'a, Queue<T>>) -> Result<MutexGuard<'a, Queue<T>>, MsgQueueError> {
+        self.parked_readers.fetch_add(1, Relaxed);
+        let woken = self.readable.wait(lock);
+        self.parked_readers.fetch_sub(1, Relaxed);
+
+        woken.map_err(|_| NoLock)
+    }
+
+    fn park_timeout<'a>(
+        &self,
+        lock: MutexGuard<'a, Queue<T>>,
+        timeout: Duration,
+    ) -> Result<MutexGuard<'a, Queue<T>>, MsgQueueError> {
+        self.parked_readers.fetch_add(1, Relaxed);
+        let woken = self.readable.wait_timeout(lock, timeout);
+        self.parked_readers.fetch_sub(1, Relaxed);
+
+        woken.map(|(lock, _)| lock).map_err(|_| NoLock)
+    }
+
+    /// Parks until a message arrives, returning `Timeout` if `timeout` passes first
+    /// 
+    /// If the queue reaches the end of transmission before then, that error is returned instead
+    pub fn read_timeout(&self, timeout: Duration) -> Result<T, MsgQueueError> {
+        self.read_deadline(Instant::now() + timeout)
+    }
+
+    /// Parks until a message arrives, returning `Timeout` if `deadline` passes first
+    /// 
+    /// Unlike a relative timeout, the deadline doesn't drift when called repeatedly in a loop
+    pub fn read_deadline(&self, deadline: Instant) -> Result<T, MsgQueueError> {
+        let mut lock = self.lock_queue()?;
+
+        loop {
+            match self.pop_locked(&mut lock, Queue::pop) {
+                Err(NoMessages) => {
+                    let now = Instant::now();
+
+                    if now >= deadline { return Err(Timeout) }
+
+                    lock = self.park_timeout(lock, deadline - now)?
+                },
+                Ok(v) => {
+                    drop(lock);
+                    return Ok(self.transform(v))
+                },
+                Err(e) => return Err(e),
+            }
+        }
+    }
+
+    /// Wakes every parked reader so they can re-check the queue
+    fn wake_readers(&self) -> Result<(), MsgQueueError> {
+        let _lock = self.lock_queue()?;
+
+        #[cfg(feature = "async")]
+        self.wake_read_futures();
+
+        self.readable.notify_all();
+
+        Ok(())
+    }
+
+    /// Reads the next message from the queue, parking until one arrives or `token` is cancelled
+    /// 
+    /// Cancelling the token makes this return `Cancelled` without affecting other readers,
+    /// though any parked readers are briefly woken to re-check the queue
+    pub fn read_cancellable(self: &Arc<Self>, token: &CancelToken) -> Result<T, MsgQueueError>
+    where T: Send + 'static {
+        let queue = Arc::downgrade(self);
+
+        let _waker = token.on_cancel(move || {
+            if let Some(queue) = queue.upgrade() { let _ = queue.wake_readers(); }
+        });
+
+        let mut lock = self.lock_queue()?;
+
+        loop {
+            if token.is_cancelled() { return Err(self.cancel_read(&lock)) }
+
+            match self.pop_locked(&mut lock, Queue::pop) {
+                Err(NoMessages) => lock = self.park(lock)?,
+                Ok(v) => {
+                    drop(lock);
+                    return Ok(self.transform(v))
+                },
+                Err(e) => return Err(e),
+            }
+        }
+    }
+
+    /// Gives up a cancelled read, which may have been woken by a send's `notify_one`,
+    /// so if a message is waiting another parked reader is woken to take it
+    fn cancel_read(&self, lock: &MutexGuard<Queue<T>>) -> MsgQueueError {
+        if lock.len() > 0 { self.readable.notify_one() }
+
+        Cancelled
+    }
+
+    /// Reads the next message from the queue, parking until one arrives or `cancel` is set
+    /// 
+    /// Nothing wakes the reader when `cancel` is set, so it re-checks the flag every
+    /// `CANCEL_CHECK_INTERVAL`, and may return `Cancelled` up to that long after it was set.
+    /// Use `read_cancellable` with a `CancelToken` to be woken immediately.
+    pub fn read_until(&self, cancel: &AtomicBool) -> Result<T, MsgQueueError> {
+        let mut lock = self.lock_queue()?;
+
+        loop {
+            if cancel.load(Relaxed) { return Err(self.cancel_read(&lock)) }
+
+            match self.pop_locked(&mut lock, Queue::pop) {
+                Err(NoMessages) => lock = self.park_timeout(lock, CANCEL_CHECK_INTERVAL)?,
+                Ok(v) => {
+                    drop(lock);
+                    return Ok(self.transform(v))
+                },
+                Err(e) => return Err(e),
+            }
+        }
+    }
+
+    /// Reads the next message from the queue
+    /// 
+    /// If there are no messages, this function will wait for one
+    /// according to the queue's `ReadStrategy`
+    pub fn read(&self) -> Result<T, MsgQueueError> {
+        self.read_with(Queue::pop).map(|t| self.transform(t))
+    }
+
+    /// An iterator that reads messages until the end of transmission, see `MsgQueueIter`
+    pub fn iter(self: &Arc<Self>) -> MsgQueueIter<T> {
+        MsgQueueIter::new(self.clone())
+    }
+
+    /// A copy of the message the next read would return, without removing it
+    /// 
+    /// The copy is taken before the queue's read transform is applied.
+    /// Returns `NoMessages` if the queue is open but empty,
+    /// and `EndOfTransmission` if it is closed and empty, without terminating it.
+    /// A closed, empty queue still waiting for readers to deregister returns `NoMessages`,
+    /// like `try_read`.
+    pub fn peek(&self) -> Result<T, MsgQueueError> where T: Clone {
+        let lock = self.lock_queue()?;
+
+        if self.is_terminated()? { return Err(QueueTerminated) }
+
+        if let Some(t) = lock.peek() { return Ok(t.clone()) }
+
+        if !self.is_closed()? || !self.readers_allow_termination()? { return Err(NoMessages) }
+
+        Err(EndOfTransmission)
+    }
+
+    /// Reads the next message without waiting
+    /// 
+    /// Returns `NoMessages` if the queue is open but empty, which leaves the queue as it is,
+    /// and an end of transmission error once it is closed and empty
+    pub fn try_read(&self) -> Result<T, MsgQueueError> {
+        self.pop(Queue::pop).map(|t| self.transform(t))
+    }
+
+    /// Reads the next message along with the epoch it was sent in
+    /// 
+    /// Readers can use this to discard messages sent before the queue was last cleared
+    pub fn read_with_epoch(&self) -> Result<(u64, T), MsgQueueError> {
+        self.read_with(Queue::pop_with_epoch).map(|(epoch, t)| (epoch, self.transform(t)))
+    }
+
+    /// Reads the next message along with its headers, which are empty if it was sent without any
+    pub fn read_with_headers(&self) -> Result<(T, Headers), MsgQueueError> {
+        self.read_with(Queue::pop_with_headers).map(|(t, headers)| (self.transform(t), headers))
+    }
+
+    /// Reads the next message along with the number of messages still buffered after it
+    pub fn read_with_remaining(&self) -> Result<(T, usize), MsgQueueError> {
+        self.read_with(|queue| queue.pop().map(|t| (t, queue.len())))
+            .map(|(t, remaining)| (self.transform(t), remaining))
+    }
+
+    /// Waits for at least one message, then reads up to `max` of the messages currently in the queue
+    /// 
+    /// Waits according to the queue's `ReadStrategy`, like `read`
+    pub fn read_batch(&self, max: usize) -> Result<Vec<T>, MsgQueueError> {
+        if max == 0 { return Ok(Vec::new()) }
+
+        let batch = self.read_with(|queue| queue.pop_up_to(max))?;
+
+        Ok(batch.into_iter().map(|t| self.transform(t)).collect())
+    }
+
+    /// Reads up to `max` of the messages currently in the queue without waiting
+    /// 
+    /// Returns an empty `Vec` if the queue is open but has no messages,
+    /// and an end of transmission error once it is closed and has no messages
+    pub fn try_read_batch(&self, max: usize) -> Result<Vec<T>, MsgQueueError> {
+        if max == 0 { return Ok(Vec::new()) }
+
+        match self.pop(|queue| queue.pop_up_to(max)) {
+            Err(NoMessages) => Ok(Vec::new()),
+            result => Ok(result?.into_iter().map(|t| self.transform(t)).collect()),
+        }
+    }
+
+    /// Waits up to `timeout` for at least `min` messages, then reads up to `max` of them
+    /// 
+    /// When the timeout passes, whatever has accumulated is returned, which may be none.
+    /// Closing the queue also cuts the wait short, since no more messages can arrive, so a
+    /// closed queue with fewer than `min` messages left returns them all straight away, and then
+    /// an end of transmission error once it is empty. A `min` above `max` is treated as `max`.
+    /// 
+    /// Sends wake one parked reader by default, so if other readers share the queue, a send that
+    /// leaves the batch short of `min` may wake this reader instead of one that would read it.
+    /// Use `WakeStrategy::All` to avoid delaying them.
+    pub fn read_batch_timeout(&self, min: usize, max: usize, timeout: Duration) -> Result<Vec<T>, MsgQueueError> {
+        if max == 0 { return Ok(Vec::new()) }
+
+        let deadline = Instant::now() + timeout;
+        let min = min.min(max);
+
+        let mut lock = self.lock_queue()?;
+
+        while lock.len() < min && self.can_send()? {
+            let now = Instant::now();
+
+            if now >= deadline { break }
+
+            lock = self.park_timeout(lock, deadline - now)?;
+        }
+
+        let batch = match self.pop_locked(&mut lock, |queue| queue.pop_up_to(max)) {
+            Err(NoMessages) => Vec::new(),
+            result => result?,
+        };
+
+        drop(lock);
+
+        Ok(batch.into_iter().map(|t| self.transform(t)).collect())
+    }
+
+    /// Locks the buffer so the caller can take every message without other readers racing in
+    /// 
+    /// Other readers and writers block until the guard is dropped.
+    /// The thread holding the guard mustn't use the queue in any other way, such as `send`
+    /// or `read`, until it drops the guard, or it will deadlock.
+    pub fn lock_for_drain(&self) -> Result<DrainGuard<'_, T>, MsgQueueError> {
+        Ok(DrainGuard::new(self, self.lock_queue()?))
+    }
+
+    /// Appends every message currently in the queue to `buf` without waiting, returning how many were added
+    /// 
+    /// Only messages present at the time of the call are drained. This reuses `buf`'s capacity,
+    /// so a consumer can recycle one buffer across calls.
+    pub fn drain_into(&self, buf: &mut Vec<T>) -> Result<usize, MsgQueueError> {
+        let start = buf.len();
+
+        let drained = self.pop(|queue| {
+            buf.extend(std::iter::from_fn(|| queue.pop()));
+
+            Some(buf.len() - start).filter(|&added| added > 0)
+        });
+
+        let added = match drained {
+            Err(NoMessages) => return Ok(0),
+            result => result?,
+        };
+
+        // The read transform runs once the lock is released, so only a queue
+        // with a transform has to move the raw messages out of `buf` first
+        if self.read_transform.is_some() {
+            let raw = buf.split_off(start);
+            buf.extend(raw.into_iter().map(|t| self.transform(t)));
+        } else {
+            (0..added).for_each(|_| self.report(|metrics| metrics.on_read()));
+        }
+
+        Ok(added)
+    }
+
+    /// Reads every message currently in the queue without waiting, in the order they would be read
+    /// 
+    /// Returns an empty `Vec` if the queue is open but empty, see `drain_into`
+    pub fn drain(&self) -> Result<Vec<T>, MsgQueueError> {
+        let mut buf = Vec::new();
+        self.drain_into(&mut buf)?;
+
+        Ok(buf)
+    }
+
+    /// Reads a message without waiting, or reports how many writers could still send one
+    /// 
+    /// The writer count is taken atomically with finding the queue empty
+    pub fn read_or_status(&self) -> Result<T, ReadStatus> {
+        let writers = self.lock_writers()?;
+        let mut lock = self.lock_queue()?;
+
+        match self.pop_locked(&mut lock, Queue::pop) {
+            Ok(t) => {
+                drop(lock);
+                drop(writers);
+                Ok(self.transform(t))
+            },
+            Err(NoMessages) => Err(ReadStatus::WouldBlock { writers_remaining: writers.len() }),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    /// Reads a message without waiting, or reports whether more messages could still arrive
+    pub fn try_read_status(&self) -> Result<T, EmptyStatus> {
+        let mut lock = self.lock_queue()?;
+
+        match self.pop_locked(&mut lock, Queue::pop) {
+            Ok(t) => {
+                drop(lock);
+                Ok(self.transform(t))
+            },
+            Err(NoMessages) if self.is_closed()? => Err(EmptyStatus::ClosedDraining),
+            Err(NoMessages) => Err(EmptyStatus::OpenEmpty),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    /// Waits according to the queue's `ReadStrategy` until `f` takes something from the buffer
+    fn read_with<R, F: FnMut(&mut Queue<T>) -> Option<R>>(&self, mut f: F) -> Result<R, MsgQueueError> {
+        let park_at = match self.read_strategy {
+            ReadStrategy::Spin => None,
+            ReadStrategy::Park => return self.read_parked(f),
+            ReadStrategy::SpinThenPark { spin } => Some(Instant::now() + spin),
+        };
+
+        loop {
+            match self.pop(&mut f) {
+                Err(NoMessages) => match park_at {
+                    Some(park_at) if Instant::now() >= park_at => return self.read_parked(f),
+                    _ => if !self.poll_interval.is_zero() {
+                        std::thread::sleep(self.poll_interval)
+                    },
+                },
+                Ok(v) => return Ok(v),
+                Err(e) => return Err(e),
+            }
+        }
+    }
+
+    /// Splits the queue into two new queues, routing each message by `pred`
+    /// 
+    /// A spawned reader drains this queue, sending messages for which `pred` is true
+    /// to the first output and the rest to the second, preserving their order.
+    /// Both outputs close once this queue reaches the end of transmission, or if forwarding fails,
+    /// which the returned `Forwarder` reports.
+    pub fn partition<F: Fn(&T) -> bool + Send + 'static>(
+        self: &Arc<Self>,
+        pred: F,
+    ) -> Result<(Arc<Self>, Arc<Self>, Forwarder), MsgQueueError>
+    where T: Send + 'static {
+        let source = self.clone();
+        let matching = AsyncMsgQueue::new_arc();
+        let rest = AsyncMsgQueue::new_arc();
+
+        let outputs = vec![
+            (matching.clone(), matching.register_writer()?),
+            (rest.clone(), rest.register_writer()?),
+        ];
+
+        let forwarder = spawn_forwarder(outputs, move || source.read(), move |msg, outputs| {
+            let (output, writer) = &outputs[!pred(&msg) as usize];
+            output.send(*writer, msg)
+        });
+
+        Ok((matching, rest, forwarder))
+    }
+
+    /// Forwards messages to a new queue at most once every `min_interval`, preserving their order
+    /// 
+    /// A spawned reader drains this queue, sleeping before each send until `min_interval` has
+    /// passed since the last one, so bursts are smoothed out for a rate-sensitive consumer.
+    /// Messages it hasn't released yet wait in this queue. Once this queue reaches the end of
+    /// transmission, the remaining messages are released at the same rate and the output closes.
+    pub fn throttle(self: &Arc<Self>, min_interval: Duration) -> Result<(Arc<Self>, Forwarder), MsgQueueError>
+    where T: Send + 'static {
+        let source = self.clone();
+        let throttled = AsyncMsgQueue::new_arc();
+        let outputs = vec![(throttled.clone(), throttled.register_writer()?)];
+
+        let mut last_sent: Option<Instant> = None;
+
+        let forwarder = spawn_forwarder(outputs, move || source.read(), move |msg, outputs| {
+            if let Some(last_sent) = last_sent {
+                std::thread::sleep(min_interval.saturating_sub(last_sent.elapsed()));
+            }
+
+            last_sent = Some(Instant::now());
+
+            let (output, writer) = &outputs[0];
+            output.send(*writer, msg)
+        });
+
+        Ok((throttled, forwarder))
+    }
+
+    /// Spawns a reader that forwards every message from this queue to `dest`
+    /// 
+    /// The forwarder registers as a writer on `dest` before this returns, and deregisters once
+    /// this queue reaches the end of transmission, so `dest` can close when its other writers finish
+    pub fn pipe_to(self: &Arc<Self>, dest: &Arc<AsyncMsgQueue<T>>) -> Forwarder
+    where T: Send + 'static {
+        let source = self.clone();
+
+        let writer = match dest.register_writer() {
+            Ok(writer) => writer,
+            Err(e) => return std::thread::spawn(move || Err(e)),
+        };
+
+        spawn_forwarder(vec![(dest.clone(), writer)], move || source.read(), |msg, outputs| {
+            let (output, writer) = &outputs[0];
+            output.send(*writer, msg)
+        })
+    }
+
+    /// Spawns a consumer that calls `f` with every message until the end of transmission,
+    /// supervising it for panics
+    /// 
+    /// The consumer is a registered reader, which deregisters when it stops. If `f` panics,
+    /// the panic is caught and `on_panic` is called with its payload before the panic resumes,
+    /// so joining the returned handle reports it as an `Err`, even if deregistering failed.
+    /// Otherwise, a failure to deregister is returned once the consumer stops.
+    /// `f` is wrapped in `AssertUnwindSafe`, so it needn't be `UnwindSafe`,
+    /// but anything it shares may be left half updated.
+    pub fn subscribe_supervised<F, P>(
+        self: &Arc<Self>,
+        mut f: F,
+        on_panic: P,
+    ) -> Result<std::thread::JoinHandle<Result<(), MsgQueueError>>, MsgQueueError>
+    where
+        T: Send + 'static,
+        F: FnMut(T) + Send + 'static,
+        P: FnOnce(&(dyn std::any::Any + Send)) + Send + 'static,
+    {
+        let queue = self.clone();
+        let reader = queue.register_reader()?;
+
+        Ok(std::thread::spawn(move || {
+            let consumed = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| loop {
+                match queue.read_as(reader) {
+                    Ok(msg) => f(msg),
+                    Err(e) if e.is_terminal() => break Ok(()),
+                    Err(e) => break Err(e),
+                }
+            }));
+
+            let deregistered = queue.deregister_reader(reader);
+
+            match consumed {
+                Ok(result) => result.and(deregistered),
+                Err(payload) => {
+                    on_panic(&*payload);
+                    std::panic::resume_unwind(payload)
+                },
+            }
+        }))
+    }
+
+    /// Reads every remaining message, folding each one into an accumulator
+    /// 
+    /// Reaching the end of transmission ends the fold successfully
+    pub fn fold<A, F: FnMut(A, T) -> A>(&self, init: A, mut f: F) -> Result<A, MsgQueueError> {
+        let mut acc = init;
+
+        loop {
+            match self.read() {
+                Ok(msg) => acc = f(acc, msg),
+                Err(e) if e.is_terminal() => return Ok(acc),
+                Err(e) => return Err(e),
+            }
+        }
+    }
+
+    /// Reads every remaining message, calling `f` on each one
+    /// 
+    /// Reaching the end of transmission returns `Ok`, while waiting longer than
+    /// `per_msg_timeout` for any one message returns `Timeout`, so a stuck writer is noticed
+    pub fn for_each_timeout<F: FnMut(T)>(&self, per_msg_timeout: Duration, mut f: F) -> Result<(), MsgQueueError> {
+        loop {
+            match self.read_deadline(Instant::now() + per_msg_timeout) {
+                Ok(msg) => f(msg),
+                Err(e) if e.is_terminal() => return Ok(()),
+                Err(e) => return Err(e),
+            }
+        }
+    }
+
+    /// Reads every remaining message, returning the smallest one
+    pub fn min(&self) -> Result<Option<T>, MsgQueueError> where T: Ord {
+        self.fold(None, |acc, msg| match acc {
+            Some(acc) => Some(std::cmp::min(acc, msg)),
+            None => Some(msg),
+        })
+    }
+
+    /// Reads every remaining message, returning the largest one
+    pub fn max(&self) -> Result<Option<T>, MsgQueueError> where T: Ord {
+        self.fold(None, |acc, msg| match acc {
+            Some(acc) => Some(std::cmp::max(acc, msg)),
+            None => Some(msg),
+        })
+    }
+}
+
+/// Queues of boxed messages, such as trait objects
+/// 
+/// ```
+/// use async_msg_queue::AsyncMsgQueue;
+/// 
+/// trait Shape { fn area(&self) -> f64; }
+/// 
+/// struct Square(f64);
+/// struct Circle(f64);
+/// 
+/// impl Shape for Square { fn area(&self) -> f64 { self.0 * self.0 } }
+/// impl Shape for Circle { fn area(&self) -> f64 { 3.0 * self.0 * self.0 } }
+/// 
+/// impl<S: Shape + Send + 'static> From<S> for Box<dyn Shape + Send> {
+///     fn from(shape: S) -> Self { Box::new(shape) }
+/// }
+/// 
+/// let queue = AsyncMsgQueue::<Box<dyn Shape + Send>>::new_boxed();
+/// 
+/// let writer_handle = queue.register_writer().unwrap();
+/// 
+/// assert_eq!(queue.send_boxed(writer_handle, Square(2.0)), Ok(()));
+/// assert_eq!(queue.send_boxed(writer_handle, Circle(1.0)), Ok(()));
+/// assert_eq!(queue.deregister_writer(writer_handle), Ok(()));
+/// 
+/// assert_eq!(queue.fold(0.0, |acc, shape| acc + shape.area()), Ok(7.0));
+/// ```
+impl<U: ?Sized> AsyncMsgQueue<Box<U>> {
+    pub fn new_boxed() -> Self { Self::new() }
+
+    /// Boxes a message and enqueues it
+    /// 
+    /// For trait objects, `item` must convert into a `Box<dyn Trait>`,
+    /// which a `From` impl for the trait can provide, as for `Box<dyn Error>`
+    pub fn send_boxed<V: Into<Box<U>>>(&self, id: WriterID, item: V) -> Result<(), MsgQueueError> {
+        self.send(id, item.into())
+    }
+}
+
+impl<A: Send + 'static, B: Send + 'static> AsyncMsgQueue<(A, B)> {
+    /// Pairs up messages from two queues, like `Iterator::zip`
+    /// 
+    /// A spawned reader takes one message from `a` and then one from `b`, sending them
+    /// together to the returned queue. It stops once either source reaches the end of
+    /// transmission or forwarding fails, closing the returned queue, and any unpaired
+    /// messages left on the other source are never read.
+    pub fn zip(a: &Arc<AsyncMsgQueue<A>>, b: &Arc<AsyncMsgQueue<B>>) -> Result<(Arc<Self>, Forwarder), MsgQueueError> {
+        let (a, b) = (a.clone(), b.clone());
+        let zipped = AsyncMsgQueue::new_arc();
+        let outputs = vec![(zipped.clone(), zipped.register_writer()?)];
+
+        let read = move || a.read().and_then(|a| Ok((a, b.read()?)));
+
+        let forwarder = spawn_forwarder(outputs, read, |pair, outputs| {
+            let (output, writer) = &outputs[0];
+            output.send(*writer, pair)
+        });
+
+        Ok((zipped, forwarder))
     }
 }
@@ -0,0 +1,101 @@
+use rand::Rng;
+use std::cell::{ Cell, RefCell };
+
+use crate::{ MsgQueueError, MsgQueueError::*, Queue, QueueState, WriterID };
+
+/// A message queue for use within a single thread
+/// 
+/// This has the same lifecycle as `AsyncMsgQueue`, but uses `RefCell` and `Cell` instead of
+/// locks and atomics, so it can't be shared between threads. Since no other thread could send
+/// a message while a reader waits, `read` returns `NoMessages` rather than waiting when the
+/// queue is open but empty.
+pub struct LocalMsgQueue<T> {
+    queue: RefCell<Queue<T>>,
+    state: Cell<QueueState>,
+    writers: RefCell<Vec<WriterID>>,
+}
+
+impl<T> Default for LocalMsgQueue<T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<T> LocalMsgQueue<T> {
+    pub fn new() -> Self {
+        Self {
+            queue: RefCell::new(Queue::new()),
+            state: Cell::new(QueueState::new()),
+            writers: RefCell::new(Vec::new()),
+        }
+    }
+
+    pub fn register_writer(&self) -> Result<WriterID, MsgQueueError> {
+        let id = rand::thread_rng().gen();
+
+        self.writers.borrow_mut().push(id);
+
+        Ok(id)
+    }
+
+    pub fn deregister_writer(&self, id: WriterID) -> Result<(), MsgQueueError> {
+        let mut writers = self.writers.borrow_mut();
+
+        let index = writers.iter()
+            .position(|&writer| writer == id)
+            .ok_or(UnknownWriter)?;
+
+        writers.remove(index);
+
+        if writers.is_empty() { self.close() }
+
+        Ok(())
+    }
+
+    pub fn is_closed(&self) -> bool {
+        !self.state.get().can_send()
+    }
+
+    pub fn is_terminated(&self) -> bool {
+        !self.state.get().can_read()
+    }
+
+    /// Closing a queue that is already closed or terminated does nothing
+    fn close(&self) {
+        if self.is_closed() { return }
+
+        self.update_state(QueueState::close)
+    }
+
+    fn update_state(&self, f: impl FnOnce(&mut QueueState)) {
+        let mut state = self.state.get();
+        f(&mut state);
+        self.state.set(state)
+    }
+
+    pub fn send(&self, id: WriterID, t: T) -> Result<(), MsgQueueError> {
+        if !self.writers.borrow().contains(&id) { return Err(UnknownWriter) }
+
+        if self.is_closed() { return Err(QueueClosed) }
+
+        self.queue.borrow_mut().push(t);
+
+        Ok(())
+    }
+
+    /// Reads the next message without waiting
+    /// 
+    /// Returns `NoMessages` if the queue is open but empty,
+    /// and an end of transmission error once it is closed and empty
+    pub fn read(&self) -> Result<T, MsgQueueError> {
+        if self.is_terminated() { return Err(QueueTerminated) }
+
+        if let Some(t) = self.queue.borrow_mut().pop() { return Ok(t) }
+
+        if !self.is_closed() { return Err(NoMessages) }
+
+        self.update_state(QueueState::terminate);
+
+        Err(EndOfTransmission)
+    }
+}
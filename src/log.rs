@@ -0,0 +1,260 @@
+use rand::Rng;
+use std::collections::{ HashMap, VecDeque };
+use std::hash::Hash;
+use std::sync::{ Arc, Condvar, Mutex, MutexGuard };
+
+use crate::{ MsgQueueError, MsgQueueError::*, QueueState, ReaderID, WriterID };
+
+/// Entries shared by several cursors, each keyed by a `K`
+pub(crate) struct Log<T, K = ReaderID> {
+    pub(crate) entries: VecDeque<T>,
+    /// The offset of `entries[0]` since the log was created
+    pub(crate) start: usize,
+    pub(crate) cursors: HashMap<K, usize>,
+    pub(crate) writers: Vec<WriterID>,
+    pub(crate) state: QueueState,
+}
+
+impl<T, K: Eq + Hash> Log<T, K> {
+    pub(crate) fn new() -> Self {
+        Self {
+            entries: VecDeque::new(),
+            start: 0,
+            cursors: HashMap::new(),
+            writers: Vec::new(),
+            state: QueueState::new(),
+        }
+    }
+
+    pub(crate) fn end(&self) -> usize {
+        self.start + self.entries.len()
+    }
+
+    /// Drops entries from the front past `max_len`, but only those every cursor has passed
+    pub(crate) fn compact(&mut self, max_len: usize) {
+        let oldest_cursor = self.cursors.values()
+            .copied()
+            .min()
+            .unwrap_or(self.end());
+
+        while self.entries.len() > max_len && self.start < oldest_cursor {
+            self.entries.pop_front();
+            self.start += 1;
+        }
+    }
+
+    /// The log terminates once it is closed and every cursor has reached the end
+    pub(crate) fn check_terminated(&mut self) {
+        let end = self.end();
+
+        if self.state == QueueState::Closed
+        && self.cursors.values().all(|&cursor| cursor == end)
+        {
+            self.state.terminate()
+        }
+    }
+}
+
+/// A message log where reading doesn't remove messages
+/// 
+/// Each reader holds its own cursor into the log, which `read` advances and `seek` moves.
+/// When the last writer deregisters the log closes, and once every reader has
+/// reached the end of a closed log it terminates.
+/// 
+/// If a maximum length is set, entries at the front of the log are discarded once
+/// every reader has read them. Entries no reader has reached yet are never discarded,
+/// so a slow reader can hold the log above its maximum length.
+pub struct LogQueue<T> {
+    log: Mutex<Log<T>>,
+    readable: Condvar,
+    max_len: Option<usize>,
+}
+
+/// A log that stores each message once, behind an `Arc`
+/// 
+/// Every reader receives a clone of the `Arc` rather than of the message,
+/// so broadcasting large messages to many readers stays cheap, and `T` needn't be `Clone`.
+pub type SharedLogQueue<T> = LogQueue<Arc<T>>;
+
+impl<T> Default for LogQueue<T> {
+    fn default() -> Self { Self::new() }
+}
+
+/// ```
+/// use async_msg_queue::{
+///     LogQueue,
+///     MsgQueueError::*
+/// };
+/// 
+/// let log = LogQueue::<usize>::new();
+/// 
+/// let reader = log.register_reader().unwrap();
+/// let writer = log.register_writer().unwrap();
+/// 
+/// assert_eq!(log.send(writer, 1), Ok(()));
+/// assert_eq!(log.send(writer, 2), Ok(()));
+/// assert_eq!(log.deregister_writer(writer), Ok(()));
+/// 
+/// assert_eq!(log.read(reader), Ok(1));
+/// assert_eq!(log.read(reader), Ok(2));
+/// 
+/// assert_eq!(log.seek(reader, 0), Ok(()));
+/// 
+/// assert_eq!(log.read(reader), Ok(1));
+/// assert_eq!(log.read(reader), Ok(2));
+/// assert_eq!(log.read(reader), Err(EndOfTransmission));
+/// assert_eq!(log.read(reader), Err(QueueTerminated));
+/// ```
+impl<T> LogQueue<T> {
+    pub fn new() -> Self {
+        Self {
+            log: Mutex::new(Log::new()),
+            readable: Condvar::new(),
+            max_len: None,
+        }
+    }
+
+    pub fn new_arc() -> Arc<Self> { Arc::new(Self::new()) }
+
+    /// Discards entries beyond `max_len` once every reader has read them
+    pub fn with_max_len(mut self, max_len: usize) -> Self {
+        self.max_len = Some(max_len);
+        self
+    }
+
+    fn lock(&self) -> Result<MutexGuard<'_, Log<T>>, MsgQueueError> {
+        crate::lock(&self.log)
+    }
+
+    pub fn register_writer(&self) -> Result<WriterID, MsgQueueError> {
+        let id = rand::thread_rng().gen();
+
+        self.lock()?.writers.push(id);
+
+        Ok(id)
+    }
+
+    pub fn deregister_writer(&self, id: WriterID) -> Result<(), MsgQueueError> {
+        let mut log = self.lock()?;
+
+        let index = log.writers.iter()
+            .position(|&writer| writer == id)
+            .ok_or(UnknownWriter)?;
+
+        log.writers.remove(index);
+
+        if log.writers.is_empty() && log.state.can_send() {
+            log.state.close();
+            log.check_terminated();
+            self.readable.notify_all();
+        }
+
+        Ok(())
+    }
+
+    /// Registers a reader with a cursor at the oldest retained entry
+    pub fn register_reader(&self) -> Result<ReaderID, MsgQueueError> {
+        let id = rand::thread_rng().gen();
+
+        let mut log = self.lock()?;
+        let start = log.start;
+
+        log.cursors.insert(id, start);
+
+        Ok(id)
+    }
+
+    pub fn deregister_reader(&self, id: ReaderID) -> Result<(), MsgQueueError> {
+        let mut log = self.lock()?;
+
+        log.cursors.remove(&id).ok_or(UnknownReader)?;
+
+        if let Some(max_len) = self.max_len { log.compact(max_len) }
+
+        log.check_terminated();
+
+        Ok(())
+    }
+
+    pub fn is_closed(&self) -> Result<bool, MsgQueueError> {
+        Ok(!self.lock()?.state.can_send())
+    }
+
+    pub fn is_terminated(&self) -> Result<bool, MsgQueueError> {
+        Ok(!self.lock()?.state.can_read())
+    }
+
+    /// Appends a message to the log
+    pub fn send(&self, id: WriterID, t: T) -> Result<(), MsgQueueError> {
+        let mut log = self.lock()?;
+
+        if !log.writers.contains(&id) { return Err(UnknownWriter) }
+        if !log.state.can_send() { return Err(QueueClosed) }
+
+        log.entries.push_back(t);
+
+        if let Some(max_len) = self.max_len { log.compact(max_len) }
+
+        self.readable.notify_all();
+
+        Ok(())
+    }
+
+    /// Reads the message at the reader's cursor and advances it
+    /// 
+    /// If the reader has read every message, this function will wait for another
+    pub fn read(&self, reader: ReaderID) -> Result<T, MsgQueueError> where T: Clone {
+        let mut log = self.lock()?;
+
+        loop {
+            if !log.state.can_read() { return Err(QueueTerminated) }
+
+            let cursor = *log.cursors.get(&reader).ok_or(UnknownReader)?;
+
+            if let Some(t) = log.entries.get(cursor - log.start) {
+                let t = t.clone();
+
+                log.cursors.insert(reader, cursor + 1);
+
+                if let Some(max_len) = self.max_len { log.compact(max_len) }
+
+                return Ok(t)
+            }
+
+            if !log.state.can_send() {
+                log.check_terminated();
+                self.readable.notify_all();
+                return Err(EndOfTransmission)
+            }
+
+            log = self.readable.wait(log).map_err(|_| NoLock)?;
+        }
+    }
+
+    /// The offset of the next message the reader will read
+    pub fn position(&self, reader: ReaderID) -> Result<usize, MsgQueueError> {
+        self.lock()?.cursors.get(&reader).copied().ok_or(UnknownReader)
+    }
+
+    /// Moves the reader's cursor to `offset`
+    /// 
+    /// Returns `OffsetUnavailable` if the entry at `offset` has been discarded,
+    /// or is past the end of the log
+    pub fn seek(&self, reader: ReaderID, offset: usize) -> Result<(), MsgQueueError> {
+        let mut log = self.lock()?;
+
+        if !log.state.can_read() { return Err(QueueTerminated) }
+        if offset < log.start || offset > log.end() { return Err(OffsetUnavailable) }
+
+        *log.cursors.get_mut(&reader).ok_or(UnknownReader)? = offset;
+
+        Ok(())
+    }
+}
+
+impl<T> LogQueue<Arc<T>> {
+    /// Appends a message to the log, wrapping it in an `Arc` to share between readers
+    pub fn send_shared(&self, id: WriterID, t: T) -> Result<(), MsgQueueError> {
+        self.send(id, Arc::new(t))
+    }
+}
@@ -0,0 +1,24 @@
+use crate::CloseReason;
+
+/// Receives events from a queue, to export them to a metrics backend
+/// 
+/// Attach an implementation with `AsyncMsgQueue::with_metrics`. Every method does nothing
+/// by default, so implementations only need the events they record.
+/// 
+/// Sends and reads are reported after the queue lock is released, except for reads through
+/// a `DrainGuard`, which holds the lock while it reads. The queue closes and
+/// terminates under its lock, so those are reported while holding it. Implementations should be
+/// quick, and mustn't use the queue.
+pub trait QueueMetrics: Send + Sync {
+    /// A message was accepted by the queue
+    fn on_send(&self) {}
+
+    /// A message was read from the queue
+    fn on_read(&self) {}
+
+    /// The queue closed, so no more messages can be sent
+    fn on_close(&self, _reason: CloseReason) {}
+
+    /// The queue terminated, so no more messages can be read
+    fn on_terminate(&self) {}
+}
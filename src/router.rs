@@ -0,0 +1,64 @@
+use std::sync::Arc;
+
+use crate::forward::spawn_forwarder;
+use crate::{ AsyncMsgQueue, Forwarder, MsgQueueError };
+
+/// Fans messages from a source queue out to several channels by their content
+/// 
+/// A spawned reader drains the source, sending each message to the channel chosen by `select`.
+/// Messages for which `select` returns an index with no channel are discarded.
+/// Every channel closes once the source reaches the end of transmission, or if forwarding fails.
+/// 
+/// ```
+/// use async_msg_queue::{ AsyncMsgQueue, Router };
+/// 
+/// let source = AsyncMsgQueue::<Result<u8, String>>::new_arc();
+/// let writer_handle = source.register_writer().unwrap();
+/// 
+/// let router = Router::new(&source, 2, |event| event.is_err() as usize).unwrap();
+/// let (values, errors) = (router.channel(0).unwrap(), router.channel(1).unwrap());
+/// 
+/// assert_eq!(source.send(writer_handle, Ok(1)), Ok(()));
+/// assert_eq!(source.send(writer_handle, Err("oops".to_string())), Ok(()));
+/// 
+/// assert_eq!(values.read(), Ok(Ok(1)));
+/// assert_eq!(errors.read(), Ok(Err("oops".to_string())));
+/// ```
+pub struct Router<E> {
+    channels: Vec<Arc<AsyncMsgQueue<E>>>,
+    forwarder: Forwarder,
+}
+
+impl<E: Send + 'static> Router<E> {
+    pub fn new<F: Fn(&E) -> usize + Send + 'static>(
+        source: &Arc<AsyncMsgQueue<E>>,
+        n_channels: usize,
+        select: F,
+    ) -> Result<Self, MsgQueueError> {
+        let source = source.clone();
+        let channels: Vec<_> = (0..n_channels).map(|_| AsyncMsgQueue::new_arc()).collect();
+
+        let outputs = channels.iter()
+            .map(|channel| Ok((channel.clone(), channel.register_writer()?)))
+            .collect::<Result<Vec<_>, _>>()?;
+
+        let forwarder = spawn_forwarder(outputs, move || source.read(), move |msg, outputs| {
+            match outputs.get(select(&msg)) {
+                Some((channel, writer)) => channel.send(*writer, msg),
+                None => Ok(()),
+            }
+        });
+
+        Ok(Self { channels, forwarder })
+    }
+
+    /// The channel receiving messages for which `select` returns `index`
+    pub fn channel(&self, index: usize) -> Option<Arc<AsyncMsgQueue<E>>> {
+        self.channels.get(index).cloned()
+    }
+
+    /// Waits for the router to stop, returning the error that stopped it if it failed to forward a message
+    pub fn join(self) -> std::thread::Result<Result<(), MsgQueueError>> {
+        self.forwarder.join()
+    }
+}
@@ -0,0 +1,124 @@
+use rand::Rng;
+use std::sync::{ Arc, Condvar, Mutex };
+use std::sync::atomic::{ AtomicUsize, Ordering::Relaxed };
+
+use crate::{ AsyncMsgQueue, MsgQueueError, MsgQueueError::*, Queue, WriterID };
+
+/// A message queue split into several shards, each with its own lock
+/// 
+/// Sends are spread across the shards in turn, and reads take from the shards in rotation,
+/// so writers and readers contend on a single lock far less often.
+/// The cost is ordering: messages are only read in the order they were sent within a shard,
+/// so two messages sent one after the other may be read in either order.
+/// 
+/// Each writer is registered on every shard, and the queue closes when the last writer deregisters.
+pub struct ShardedMsgQueue<T> {
+    pub(crate) shards: Vec<AsyncMsgQueue<T>>,
+    next_send: AtomicUsize,
+    next_read: AtomicUsize,
+    /// Bumped whenever a shard gains a message or closes, so parked readers know to look again
+    generation: Mutex<u64>,
+    readable: Condvar,
+}
+
+impl<T> ShardedMsgQueue<T> {
+    /// Creates a queue with `n_shards` shards, or one if `n_shards` is 0
+    pub fn new(n_shards: usize) -> Self {
+        Self {
+            shards: (0..n_shards.max(1)).map(|_| AsyncMsgQueue::new()).collect(),
+            next_send: AtomicUsize::new(0),
+            next_read: AtomicUsize::new(0),
+            generation: Mutex::new(0),
+            readable: Condvar::new(),
+        }
+    }
+
+    pub fn new_arc(n_shards: usize) -> Arc<Self> { Arc::new(Self::new(n_shards)) }
+
+    pub fn shard_count(&self) -> usize {
+        self.shards.len()
+    }
+
+    /// Registers a writer on every shard, or on none if any shard fails to register it
+    pub fn register_writer(&self) -> Result<WriterID, MsgQueueError> {
+        let id = rand::thread_rng().gen();
+
+        for (registered, shard) in self.shards.iter().enumerate() {
+            if let Err(e) = shard.register_writer_as(id) {
+                for shard in &self.shards[..registered] {
+                    let _ = shard.unregister_writer_as(id);
+                }
+
+                return Err(e)
+            }
+        }
+
+        Ok(id)
+    }
+
+    /// Deregisters a writer from every shard, even if deregistering from an earlier one fails
+    pub fn deregister_writer(&self, id: WriterID) -> Result<(), MsgQueueError> {
+        let deregistered = self.shards.iter()
+            .map(|shard| shard.deregister_writer(id))
+            .fold(Ok(()), Result::and);
+
+        self.wake_readers(Condvar::notify_all)?;
+
+        deregistered
+    }
+
+    pub fn is_closed(&self) -> Result<bool, MsgQueueError> {
+        self.shards[0].is_closed()
+    }
+
+    pub fn send(&self, id: WriterID, t: T) -> Result<(), MsgQueueError> {
+        let shard = self.next_send.fetch_add(1, Relaxed) % self.shards.len();
+
+        self.shards[shard].send(id, t)?;
+
+        self.wake_readers(Condvar::notify_one)
+    }
+
+    fn wake_readers(&self, notify: fn(&Condvar)) -> Result<(), MsgQueueError> {
+        *crate::lock(&self.generation)? += 1;
+        notify(&self.readable);
+
+        Ok(())
+    }
+
+    /// Reads a message from the next shard that has one, parking until one arrives
+    /// 
+    /// Returns an end of transmission error once every shard has ended
+    pub fn read(&self) -> Result<T, MsgQueueError> {
+        loop {
+            let generation = *crate::lock(&self.generation)?;
+
+            let start = self.next_read.fetch_add(1, Relaxed);
+            let mut ended = 0;
+            let mut end_of_transmission = false;
+
+            for i in 0..self.shards.len() {
+                match self.shards[(start + i) % self.shards.len()].pop(Queue::pop) {
+                    Ok(t) => return Ok(t),
+                    Err(NoMessages) => (),
+                    Err(EndOfTransmission) => {
+                        ended += 1;
+                        end_of_transmission = true
+                    },
+                    Err(QueueTerminated) => ended += 1,
+                    Err(e) => return Err(e),
+                }
+            }
+
+            if ended == self.shards.len() {
+                return Err(if end_of_transmission { EndOfTransmission } else { QueueTerminated })
+            }
+
+            let mut current = crate::lock(&self.generation)?;
+
+            while *current == generation {
+                current = self.readable.wait(current).map_err(|_| NoLock)?;
+            }
+        }
+    }
+}
@@ -0,0 +1,34 @@
+/// The message queue can be in one of three states:
+/// - Open - The message queue can recieve more messages
+/// - Closed - The message queue can recieve no more messages
+/// - Terminated - The message queue has been closed and all the messages have been read
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum QueueState {
+    Open,
+    Closed,
+    Terminated,
+}
+
+impl QueueState {
+    pub(crate) fn new() -> Self { Self::Open }
+
+    pub(crate) fn close(&mut self) {
+        *self = Self::Closed
+    }
+
+    pub(crate) fn reopen(&mut self) {
+        *self = Self::Open
+    }
+
+    pub(crate) fn terminate(&mut self) {
+        *self = Self::Terminated
+    }
+
+    pub(crate) fn can_send(&self) -> bool {
+        *self == Self::Open
+    }
+
+    pub(crate) fn can_read(&self) -> bool {
+        *self != Self::Terminated
+    }
+}
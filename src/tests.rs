@@ -251,4 +251,2607 @@ pub fn two_writers_two_readers() {
     let result2 = result2.unwrap();
 
     assert_eq!(result1 + result2, 1 + 2 + 3 + 4 + 5 + 6);
-}
\ No newline at end of file
+}
+
+#[test]
+pub fn fold_sums_messages() {
+    let queue = AsyncMsgQueue::<usize>::new_arc();
+    let reader = queue.clone();
+
+    let reader_handle = std::thread::spawn(move || {
+        reader.fold(0, |acc, msg| acc + msg)
+    });
+
+    let writer_handle = queue.register_writer().unwrap();
+
+    for n in 0..100 {
+        assert_eq!(queue.send(writer_handle, n), Ok(()));
+    }
+
+    assert_eq!(queue.deregister_writer(writer_handle), Ok(()));
+
+    let result = reader_handle.join();
+
+    assert!(result.is_ok());
+
+    assert_eq!(result.unwrap(), Ok((0..100).sum()));
+}
+
+#[test]
+pub fn min_and_max() {
+    let queue = AsyncMsgQueue::<usize>::new();
+    let writer_handle = queue.register_writer().unwrap();
+
+    for n in [4, 2, 7, 5] {
+        assert_eq!(queue.send(writer_handle, n), Ok(()));
+    }
+
+    assert_eq!(queue.deregister_writer(writer_handle), Ok(()));
+
+    assert_eq!(queue.max(), Ok(Some(7)));
+
+    let queue = AsyncMsgQueue::<usize>::new();
+    let writer_handle = queue.register_writer().unwrap();
+
+    for n in [4, 2, 7, 5] {
+        assert_eq!(queue.send(writer_handle, n), Ok(()));
+    }
+
+    assert_eq!(queue.deregister_writer(writer_handle), Ok(()));
+
+    assert_eq!(queue.min(), Ok(Some(2)));
+}
+
+#[test]
+pub fn terminate_at_deadline() {
+    let queue = AsyncMsgQueue::<usize>::new_arc();
+    let reader = queue.clone();
+
+    let writer_handle = queue.register_writer().unwrap();
+
+    assert_eq!(queue.send(writer_handle, 1), Ok(()));
+
+    let reader_handle = std::thread::spawn(move || {
+        let mut messages = vec![];
+
+        loop {
+            match reader.read() {
+                Ok(msg) => messages.push(msg),
+                Err(e) => return (messages, e),
+            }
+        }
+    });
+
+    let deadline = std::time::Instant::now() + std::time::Duration::from_millis(20);
+
+    assert_eq!(queue.terminate_at(deadline), Ok(()));
+
+    let result = reader_handle.join();
+
+    assert!(result.is_ok());
+
+    assert_eq!(result.unwrap(), (vec![1], QueueTerminated));
+    assert_eq!(queue.send(writer_handle, 2), Err(QueueClosed));
+}
+
+#[test]
+pub fn terminate_at_reschedules() {
+    let queue = AsyncMsgQueue::<usize>::new_arc();
+    let now = std::time::Instant::now();
+
+    assert_eq!(queue.terminate_at(now + std::time::Duration::from_millis(10)), Ok(()));
+    assert_eq!(queue.terminate_at(now + std::time::Duration::from_secs(3600)), Ok(()));
+
+    std::thread::sleep(std::time::Duration::from_millis(50));
+
+    assert_eq!(queue.is_terminated(), Ok(false));
+}
+
+#[test]
+pub fn terminate_at_shares_one_timer_thread() {
+    let queue = AsyncMsgQueue::<usize>::new_arc();
+    let deadline = queue.deadline.0.clone();
+    let now = std::time::Instant::now();
+
+    for secs in [3600, 1800, 7200] {
+        assert_eq!(queue.terminate_at(now + std::time::Duration::from_secs(secs)), Ok(()));
+    }
+
+    assert_eq!(Arc::strong_count(&deadline), 3);
+
+    drop(queue);
+    std::thread::sleep(std::time::Duration::from_millis(50));
+
+    assert_eq!(Arc::strong_count(&deadline), 1);
+}
+
+#[test]
+pub fn keyed_queue_keeps_latest_value() {
+    let queue = KeyedQueue::<usize, usize>::new_arc();
+    let reader = queue.clone();
+
+    let writer_handle = queue.register_writer().unwrap();
+
+    assert_eq!(queue.send_keyed(writer_handle, 1, 10), Ok(()));
+    assert_eq!(queue.send_keyed(writer_handle, 2, 20), Ok(()));
+    assert_eq!(queue.send_keyed(writer_handle, 1, 11), Ok(()));
+    assert_eq!(queue.send_keyed(writer_handle, 3, 30), Ok(()));
+    assert_eq!(queue.send_keyed(writer_handle, 2, 21), Ok(()));
+
+    assert_eq!(queue.deregister_writer(writer_handle), Ok(()));
+
+    let reader_handle = std::thread::spawn(move || {
+        let mut messages = vec![];
+
+        loop {
+            match reader.read() {
+                Ok(msg) => messages.push(msg),
+                Err(EndOfTransmission) |
+                Err(QueueTerminated) => return Ok(messages),
+                Err(e) => return Err(e)
+            }
+        }
+    });
+
+    let result = reader_handle.join();
+
+    assert!(result.is_ok());
+
+    assert_eq!(result.unwrap(), Ok(vec![(1, 11), (2, 21), (3, 30)]));
+}
+
+#[test]
+pub fn deregister_reports_closing_writer() {
+    let queue = AsyncMsgQueue::<usize>::new();
+
+    let writer1_handle = queue.register_writer().unwrap();
+    let writer2_handle = queue.register_writer().unwrap();
+
+    assert_eq!(queue.deregister_writer_reporting(writer1_handle), Ok(false));
+    assert_eq!(queue.is_closed(), Ok(false));
+
+    assert_eq!(queue.deregister_writer_reporting(writer2_handle), Ok(true));
+    assert_eq!(queue.is_closed(), Ok(true));
+}
+
+#[test]
+pub fn parked_reader_wakes_on_send() {
+    for strategy in [
+        ReadStrategy::Park,
+        ReadStrategy::SpinThenPark { spin: std::time::Duration::from_millis(1) },
+    ] {
+        let queue = Arc::new(AsyncMsgQueue::<usize>::new().with_read_strategy(strategy));
+        let reader = queue.clone();
+
+        let writer_handle = queue.register_writer().unwrap();
+
+        let reader_handle = std::thread::spawn(move || reader.read());
+
+        std::thread::sleep(std::time::Duration::from_millis(10));
+
+        assert_eq!(queue.send(writer_handle, 1), Ok(()));
+
+        let result = reader_handle.join();
+
+        assert!(result.is_ok());
+
+        assert_eq!(result.unwrap(), Ok(1));
+    }
+}
+
+#[test]
+pub fn parked_readers_wake_on_close() {
+    let queue = Arc::new(AsyncMsgQueue::<usize>::new().with_read_strategy(ReadStrategy::Park));
+    let reader1 = queue.clone();
+    let reader2 = queue.clone();
+
+    let writer_handle = queue.register_writer().unwrap();
+
+    let reader_handle1 = std::thread::spawn(move || reader1.read());
+    let reader_handle2 = std::thread::spawn(move || reader2.read());
+
+    std::thread::sleep(std::time::Duration::from_millis(10));
+
+    assert_eq!(queue.deregister_writer(writer_handle), Ok(()));
+
+    let mut results = vec![
+        reader_handle1.join().unwrap(),
+        reader_handle2.join().unwrap(),
+    ];
+
+    results.sort_by_key(|result| result == &Err(QueueTerminated));
+
+    assert_eq!(results, vec![Err(EndOfTransmission), Err(QueueTerminated)]);
+}
+
+#[test]
+pub fn read_with_remaining_counts_buffer() {
+    let queue = AsyncMsgQueue::<usize>::new();
+    let writer_handle = queue.register_writer().unwrap();
+
+    for n in 0..3 {
+        assert_eq!(queue.send(writer_handle, n), Ok(()));
+    }
+
+    assert_eq!(queue.deregister_writer(writer_handle), Ok(()));
+
+    assert_eq!(queue.read_with_remaining(), Ok((0, 2)));
+    assert_eq!(queue.read_with_remaining(), Ok((1, 1)));
+    assert_eq!(queue.read_with_remaining(), Ok((2, 0)));
+    assert_eq!(queue.read_with_remaining(), Err(EndOfTransmission));
+}
+
+#[test]
+pub fn reaper_deregisters_silent_writers() {
+    let timeout = std::time::Duration::from_millis(20);
+    let queue = Arc::new(AsyncMsgQueue::<usize>::new().with_writer_timeout(timeout));
+
+    let live_writer = queue.register_writer().unwrap();
+    let silent_writer = queue.register_writer().unwrap();
+
+    let reaper_handle = queue.spawn_writer_reaper();
+
+    for _ in 0..20 {
+        std::thread::sleep(timeout / 4);
+        assert_eq!(queue.heartbeat(live_writer), Ok(()));
+    }
+
+    assert_eq!(queue.send(silent_writer, 1), Err(UnknownWriter));
+    assert_eq!(queue.send(live_writer, 2), Ok(()));
+    assert_eq!(queue.is_closed(), Ok(false));
+
+    let result = reaper_handle.join();
+
+    assert!(result.is_ok());
+    assert_eq!(result.unwrap(), Ok(()));
+
+    assert_eq!(queue.is_closed(), Ok(true));
+    assert_eq!(queue.read(), Ok(2));
+}
+
+#[test]
+pub fn partition_routes_by_predicate() {
+    let queue = AsyncMsgQueue::<usize>::new_arc();
+
+    let partitioned = queue.partition(|n| n % 2 == 0);
+
+    assert!(partitioned.is_ok());
+
+    let (evens, odds, forwarder) = partitioned.unwrap();
+
+    let writer_handle = queue.register_writer().unwrap();
+
+    for n in 0..10 {
+        assert_eq!(queue.send(writer_handle, n), Ok(()));
+    }
+
+    assert_eq!(queue.deregister_writer(writer_handle), Ok(()));
+
+    let collect = |queue: Arc<AsyncMsgQueue<usize>>| queue.fold(vec![], |mut acc, n| {
+        acc.push(n);
+        acc
+    });
+
+    assert_eq!(collect(evens), Ok(vec![0, 2, 4, 6, 8]));
+    assert_eq!(collect(odds), Ok(vec![1, 3, 5, 7, 9]));
+    assert_eq!(forwarder.join().unwrap(), Ok(()));
+}
+
+#[test]
+pub fn capacity_accessors() {
+    let unbounded = AsyncMsgQueue::<usize>::new();
+
+    assert_eq!(unbounded.capacity(), None);
+    assert_eq!(unbounded.remaining_capacity(), Ok(None));
+
+    let queue = AsyncMsgQueue::<usize>::new().with_capacity(2);
+    let writer_handle = queue.register_writer().unwrap();
+
+    assert_eq!(queue.capacity(), Some(2));
+    assert_eq!(queue.remaining_capacity(), Ok(Some(2)));
+
+    assert_eq!(queue.send(writer_handle, 1), Ok(()));
+    assert_eq!(queue.remaining_capacity(), Ok(Some(1)));
+
+    assert_eq!(queue.send(writer_handle, 2), Ok(()));
+    assert_eq!(queue.remaining_capacity(), Ok(Some(0)));
+    assert_eq!(queue.send(writer_handle, 3), Err(QueueFull));
+
+    assert_eq!(queue.read(), Ok(1));
+    assert_eq!(queue.remaining_capacity(), Ok(Some(1)));
+    assert_eq!(queue.capacity(), Some(2));
+}
+
+#[test]
+pub fn send_handoff_reports_parked_reader() {
+    let queue = Arc::new(AsyncMsgQueue::<usize>::new().with_read_strategy(ReadStrategy::Park));
+    let reader = queue.clone();
+
+    let writer_handle = queue.register_writer().unwrap();
+
+    assert_eq!(queue.send_handoff(writer_handle, 1), Ok(false));
+    assert_eq!(queue.read(), Ok(1));
+
+    let reader_handle = std::thread::spawn(move || reader.read());
+
+    std::thread::sleep(std::time::Duration::from_millis(20));
+
+    assert_eq!(queue.send_handoff(writer_handle, 2), Ok(true));
+
+    let result = reader_handle.join();
+
+    assert!(result.is_ok());
+    assert_eq!(result.unwrap(), Ok(2));
+}
+
+#[test]
+pub fn log_queue_readers_see_every_message() {
+    let log = LogQueue::<usize>::new_arc();
+
+    let reader1 = log.register_reader().unwrap();
+    let reader2 = log.register_reader().unwrap();
+
+    let reader_handles: Vec<_> = [reader1, reader2].into_iter().map(|reader| {
+        let log = log.clone();
+
+        std::thread::spawn(move || {
+            let mut messages = vec![];
+
+            loop {
+                match log.read(reader) {
+                    Ok(msg) => messages.push(msg),
+                    Err(EndOfTransmission) |
+                    Err(QueueTerminated) => return Ok(messages),
+                    Err(e) => return Err(e)
+                }
+            }
+        })
+    }).collect();
+
+    let writer_handle = log.register_writer().unwrap();
+
+    for n in 0..10 {
+        assert_eq!(log.send(writer_handle, n), Ok(()));
+    }
+
+    assert_eq!(log.deregister_writer(writer_handle), Ok(()));
+
+    for handle in reader_handles {
+        let result = handle.join();
+
+        assert!(result.is_ok());
+        assert_eq!(result.unwrap(), Ok((0..10).collect()));
+    }
+
+    assert_eq!(log.is_terminated(), Ok(true));
+}
+
+#[test]
+pub fn log_queue_compacts_read_entries() {
+    let log = LogQueue::<usize>::new().with_max_len(2);
+
+    let fast_reader = log.register_reader().unwrap();
+    let slow_reader = log.register_reader().unwrap();
+    let writer_handle = log.register_writer().unwrap();
+
+    for n in 0..5 {
+        assert_eq!(log.send(writer_handle, n), Ok(()));
+    }
+
+    for n in 0..5 {
+        assert_eq!(log.read(fast_reader), Ok(n));
+    }
+
+    assert_eq!(log.read(slow_reader), Ok(0));
+    assert_eq!(log.read(slow_reader), Ok(1));
+
+    assert_eq!(log.seek(slow_reader, 0), Err(OffsetUnavailable));
+    assert_eq!(log.seek(slow_reader, 6), Err(OffsetUnavailable));
+    assert_eq!(log.seek(fast_reader, 3), Ok(()));
+    assert_eq!(log.position(fast_reader), Ok(3));
+    assert_eq!(log.read(fast_reader), Ok(3));
+}
+
+#[test]
+pub fn barrier_reached_after_earlier_messages() {
+    let queue = AsyncMsgQueue::<usize>::new();
+
+    let writer1_handle = queue.register_writer().unwrap();
+    let writer2_handle = queue.register_writer().unwrap();
+
+    assert_eq!(queue.send(writer1_handle, 1), Ok(()));
+    assert_eq!(queue.send(writer2_handle, 2), Ok(()));
+
+    let barrier = queue.barrier(writer1_handle);
+
+    assert!(barrier.is_ok());
+
+    let barrier = barrier.unwrap();
+
+    assert_eq!(queue.send(writer2_handle, 3), Ok(()));
+
+    assert_eq!(queue.reached_barrier(barrier), Ok(false));
+    assert_eq!(queue.read(), Ok(1));
+    assert_eq!(queue.reached_barrier(barrier), Ok(false));
+    assert_eq!(queue.read(), Ok(2));
+    assert_eq!(queue.reached_barrier(barrier), Ok(true));
+    assert_eq!(queue.read(), Ok(3));
+    assert_eq!(queue.reached_barrier(barrier), Ok(true));
+}
+
+#[test]
+pub fn poll_interval_reader_receives_messages() {
+    let interval = std::time::Duration::from_millis(5);
+    let queue = Arc::new(AsyncMsgQueue::<usize>::new().with_poll_interval(interval));
+    let reader = queue.clone();
+
+    let reader_handle = std::thread::spawn(move || reader.fold(0, |acc, n| acc + n));
+
+    let writer_handle = queue.register_writer().unwrap();
+
+    for n in 0..5 {
+        std::thread::sleep(interval);
+        assert_eq!(queue.send(writer_handle, n), Ok(()));
+    }
+
+    assert_eq!(queue.deregister_writer(writer_handle), Ok(()));
+
+    let result = reader_handle.join();
+
+    assert!(result.is_ok());
+    assert_eq!(result.unwrap(), Ok(10));
+}
+
+#[test]
+pub fn try_read_batch_takes_available_messages() {
+    let queue = AsyncMsgQueue::<usize>::new();
+    let writer_handle = queue.register_writer().unwrap();
+
+    assert_eq!(queue.try_read_batch(3), Ok(vec![]));
+
+    for n in 0..5 {
+        assert_eq!(queue.send(writer_handle, n), Ok(()));
+    }
+
+    assert_eq!(queue.try_read_batch(3), Ok(vec![0, 1, 2]));
+    assert_eq!(queue.try_read_batch(3), Ok(vec![3, 4]));
+    assert_eq!(queue.try_read_batch(3), Ok(vec![]));
+
+    assert_eq!(queue.deregister_writer(writer_handle), Ok(()));
+
+    assert_eq!(queue.try_read_batch(3), Err(EndOfTransmission));
+    assert_eq!(queue.try_read_batch(3), Err(QueueTerminated));
+}
+
+#[test]
+pub fn terminal_errors() {
+    assert!(EndOfTransmission.is_terminal());
+    assert!(QueueTerminated.is_terminal());
+    assert!(!NoMessages.is_terminal());
+    assert!(!QueueClosed.is_terminal());
+    assert!(!NoLock.is_terminal());
+}
+
+#[cfg(feature = "bench")]
+#[test]
+pub fn throughput_harness_reads_every_message() {
+    let report = bench::run_throughput(|| AsyncMsgQueue::new().with_capacity(16), 3, 2, 1000);
+
+    assert!(report.is_ok());
+
+    let report = report.unwrap();
+
+    assert_eq!(report.messages, 1000);
+    assert!(report.p50_latency <= report.p99_latency);
+}
+
+#[cfg(feature = "bench")]
+#[test]
+pub fn throughput_harness_rejects_zero_writers_or_readers() {
+    let report = bench::run_throughput(AsyncMsgQueue::new, 0, 1, 10);
+
+    assert_eq!(report.err(), Some(NegativeWriters));
+
+    let report = bench::run_throughput(AsyncMsgQueue::new, 1, 0, 10);
+
+    assert_eq!(report.err(), Some(NegativeReaders));
+}
+
+#[test]
+pub fn cancel_token_stops_one_reader() {
+    let queue = AsyncMsgQueue::<usize>::new_arc();
+    let cancelled_reader = queue.clone();
+    let other_reader = queue.clone();
+
+    let writer_handle = queue.register_writer().unwrap();
+
+    let token = CancelToken::new();
+    let reader_token = token.clone();
+
+    let cancelled_handle = std::thread::spawn(move || {
+        cancelled_reader.read_cancellable(&reader_token)
+    });
+
+    let other_handle = std::thread::spawn(move || {
+        other_reader.read_cancellable(&CancelToken::new())
+    });
+
+    std::thread::sleep(std::time::Duration::from_millis(20));
+
+    token.cancel();
+
+    let result = cancelled_handle.join();
+
+    assert!(result.is_ok());
+    assert_eq!(result.unwrap(), Err(Cancelled));
+
+    assert_eq!(queue.send(writer_handle, 1), Ok(()));
+
+    let result = other_handle.join();
+
+    assert!(result.is_ok());
+    assert_eq!(result.unwrap(), Ok(1));
+}
+
+#[test]
+pub fn send_front_is_read_next() {
+    let queue = AsyncMsgQueue::<usize>::new();
+    let writer_handle = queue.register_writer().unwrap();
+
+    assert_eq!(queue.send(writer_handle, 1), Ok(()));
+    assert_eq!(queue.send(writer_handle, 2), Ok(()));
+
+    let barrier = queue.barrier(writer_handle).unwrap();
+
+    assert_eq!(queue.send_front(writer_handle, 10), Ok(()));
+    assert_eq!(queue.send_front(writer_handle, 11), Ok(()));
+    assert_eq!(queue.send(writer_handle, 3), Ok(()));
+
+    assert_eq!(queue.read(), Ok(11));
+    assert_eq!(queue.read(), Ok(10));
+    assert_eq!(queue.read(), Ok(1));
+    assert_eq!(queue.reached_barrier(barrier), Ok(false));
+    assert_eq!(queue.read(), Ok(2));
+    assert_eq!(queue.reached_barrier(barrier), Ok(true));
+    assert_eq!(queue.read(), Ok(3));
+}
+
+#[test]
+pub fn clear_starts_new_epoch() {
+    let queue = AsyncMsgQueue::<usize>::new();
+    let writer_handle = queue.register_writer().unwrap();
+
+    assert_eq!(queue.epoch(), Ok(0));
+    assert_eq!(queue.send(writer_handle, 1), Ok(()));
+    assert_eq!(queue.send(writer_handle, 2), Ok(()));
+    assert_eq!(queue.read_with_epoch(), Ok((0, 1)));
+
+    assert_eq!(queue.clear(), Ok(()));
+    assert_eq!(queue.epoch(), Ok(1));
+
+    assert_eq!(queue.send(writer_handle, 3), Ok(()));
+    assert_eq!(queue.read_with_epoch(), Ok((1, 3)));
+    assert_eq!(queue.is_closed(), Ok(false));
+}
+
+#[test]
+pub fn mpsc_channel_disconnects_when_senders_drop() {
+    use compat::mpsc::{ channel, RecvTimeoutError, TryRecvError };
+
+    let (sender, receiver) = channel::<usize>();
+    let sender2 = sender.clone();
+
+    assert_eq!(receiver.try_recv(), Err(TryRecvError::Empty));
+    assert_eq!(
+        receiver.recv_timeout(std::time::Duration::from_millis(10)),
+        Err(RecvTimeoutError::Timeout),
+    );
+
+    let handle = std::thread::spawn(move || {
+        for n in 0..5 {
+            sender2.send(n).unwrap();
+        }
+    });
+
+    assert_eq!(sender.send(5), Ok(()));
+    drop(sender);
+
+    assert!(handle.join().is_ok());
+
+    let mut messages: Vec<usize> = receiver.iter().collect();
+    messages.sort();
+
+    assert_eq!(messages, (0..6).collect::<Vec<_>>());
+    assert_eq!(receiver.try_recv(), Err(TryRecvError::Disconnected));
+}
+
+#[test]
+pub fn mpsc_sync_channel_and_dropped_receiver() {
+    use compat::mpsc::{ sync_channel, SendError, TrySendError };
+
+    let (sender, receiver) = sync_channel::<usize>(1);
+
+    assert_eq!(sender.try_send(1), Ok(()));
+    assert_eq!(sender.try_send(2), Err(TrySendError::Full(2)));
+
+    let blocked_sender = sender.clone();
+    let handle = std::thread::spawn(move || blocked_sender.send(3));
+
+    assert_eq!(receiver.recv(), Ok(1));
+    assert_eq!(receiver.recv(), Ok(3));
+    assert_eq!(handle.join().unwrap(), Ok(()));
+
+    drop(receiver);
+
+    assert_eq!(sender.send(4), Err(SendError(4)));
+}
+
+#[test]
+pub fn read_transform_applies_to_each_message() {
+    let queue = AsyncMsgQueue::<usize>::new().with_read_transform(|n| n * 10);
+    let writer_handle = queue.register_writer().unwrap();
+
+    for n in 1..=4 {
+        assert_eq!(queue.send(writer_handle, n), Ok(()));
+    }
+
+    assert_eq!(queue.read(), Ok(10));
+    assert_eq!(queue.read_with_remaining(), Ok((20, 2)));
+    assert_eq!(queue.try_read_batch(5), Ok(vec![30, 40]));
+}
+
+#[test]
+pub fn sent_and_read_counts() {
+    let queue = AsyncMsgQueue::<usize>::new();
+    let writer_handle = queue.register_writer().unwrap();
+
+    for n in 0..5 {
+        assert_eq!(queue.send(writer_handle, n), Ok(()));
+    }
+
+    assert_eq!(queue.read(), Ok(0));
+    assert_eq!(queue.try_read_batch(2), Ok(vec![1, 2]));
+
+    assert_eq!(queue.sent_count(), 5);
+    assert_eq!(queue.read_count(), 3);
+
+    assert_eq!(queue.clear(), Ok(()));
+    assert_eq!(queue.deregister_writer(writer_handle), Ok(()));
+
+    assert_eq!(queue.sent_count(), 5);
+    assert_eq!(queue.read_count(), 3);
+}
+
+#[test]
+pub fn into_vec_returns_unread_messages() {
+    let queue = AsyncMsgQueue::<String>::new();
+    let writer_handle = queue.register_writer().unwrap();
+
+    for message in ["msg1", "msg2", "msg3"] {
+        assert_eq!(queue.send(writer_handle, message.into()), Ok(()));
+    }
+
+    assert_eq!(queue.send_front(writer_handle, "urgent".into()), Ok(()));
+    assert_eq!(queue.read(), Ok("urgent".into()));
+    assert_eq!(queue.read(), Ok("msg1".into()));
+
+    assert_eq!(queue.into_vec(), vec!["msg2".to_string(), "msg3".into()]);
+}
+
+#[test]
+pub fn starved_readers_are_reported() {
+    let queue = AsyncMsgQueue::<usize>::new();
+    let writer_handle = queue.register_writer().unwrap();
+
+    let busy_reader = queue.register_reader().unwrap();
+    let idle_reader = queue.register_reader().unwrap();
+
+    let threshold = std::time::Duration::from_millis(20);
+
+    assert_eq!(queue.starved_readers(threshold), Ok(vec![]));
+
+    std::thread::sleep(threshold * 2);
+
+    assert_eq!(queue.send(writer_handle, 1), Ok(()));
+    assert_eq!(queue.read_as(busy_reader), Ok(1));
+
+    assert_eq!(queue.starved_readers(threshold), Ok(vec![idle_reader]));
+
+    assert_eq!(queue.deregister_reader(idle_reader), Ok(()));
+    assert_eq!(queue.read_as(idle_reader), Err(UnknownReader));
+    assert_eq!(queue.starved_readers(threshold), Ok(vec![]));
+}
+
+#[test]
+pub fn boxed_trait_object_messages() {
+    let queue = AsyncMsgQueue::<Box<dyn std::error::Error + Send + Sync>>::new_boxed();
+    let writer_handle = queue.register_writer().unwrap();
+
+    assert_eq!(queue.send_boxed(writer_handle, "first"), Ok(()));
+    assert_eq!(queue.send_boxed(writer_handle, std::fmt::Error), Ok(()));
+    assert_eq!(queue.deregister_writer(writer_handle), Ok(()));
+
+    let messages = queue.fold(vec![], |mut acc, e| {
+        acc.push(e.to_string());
+        acc
+    });
+
+    assert_eq!(messages, Ok(vec!["first".into(), std::fmt::Error.to_string()]));
+}
+
+#[test]
+pub fn extract_removes_matching_messages() {
+    let queue = AsyncMsgQueue::<usize>::new();
+    let writer_handle = queue.register_writer().unwrap();
+
+    for n in 0..10 {
+        assert_eq!(queue.send(writer_handle, n), Ok(()));
+    }
+
+    assert_eq!(queue.send_front(writer_handle, 12), Ok(()));
+    assert_eq!(queue.send_front(writer_handle, 11), Ok(()));
+
+    assert_eq!(queue.extract(|n| n % 3 == 0), Ok(vec![12, 0, 3, 6, 9]));
+    assert_eq!(queue.is_closed(), Ok(false));
+
+    assert_eq!(queue.try_read_batch(10), Ok(vec![11, 1, 2, 4, 5, 7, 8]));
+}
+
+#[test]
+pub fn overflow_policies() {
+    let queue = AsyncMsgQueue::<usize>::new().with_capacity_policy(2, OverflowPolicy::DropOldest);
+    let writer_handle = queue.register_writer().unwrap();
+
+    for n in 0..5 {
+        assert_eq!(queue.send(writer_handle, n), Ok(()));
+    }
+
+    assert_eq!(queue.dropped_count(), 3);
+    assert_eq!(queue.try_read_batch(5), Ok(vec![3, 4]));
+
+    let queue = AsyncMsgQueue::<usize>::new().with_capacity_policy(2, OverflowPolicy::DropNewest);
+    let writer_handle = queue.register_writer().unwrap();
+
+    for n in 0..5 {
+        assert_eq!(queue.send(writer_handle, n), Ok(()));
+    }
+
+    assert_eq!(queue.dropped_count(), 3);
+    assert_eq!(queue.try_read_batch(5), Ok(vec![0, 1]));
+
+    let queue = AsyncMsgQueue::<usize>::new().with_capacity_policy(2, OverflowPolicy::Error);
+    let writer_handle = queue.register_writer().unwrap();
+
+    assert_eq!(queue.send(writer_handle, 0), Ok(()));
+    assert_eq!(queue.send(writer_handle, 1), Ok(()));
+    assert_eq!(queue.send(writer_handle, 2), Err(QueueFull));
+    assert_eq!(queue.dropped_count(), 0);
+}
+
+#[test]
+pub fn block_policy_waits_for_space() {
+    let queue = Arc::new(AsyncMsgQueue::<usize>::new().with_capacity_policy(1, OverflowPolicy::Block));
+    let writer = queue.clone();
+
+    let writer_handle = queue.register_writer().unwrap();
+
+    assert_eq!(queue.send(writer_handle, 0), Ok(()));
+    assert_eq!(queue.try_send(writer_handle, 1), Err(QueueFull));
+
+    let sender_handle = std::thread::spawn(move || writer.send(writer_handle, 1));
+
+    std::thread::sleep(std::time::Duration::from_millis(20));
+
+    assert!(!sender_handle.is_finished());
+    assert_eq!(queue.read(), Ok(0));
+
+    let result = sender_handle.join();
+
+    assert!(result.is_ok());
+    assert_eq!(result.unwrap(), Ok(()));
+    assert_eq!(queue.read(), Ok(1));
+}
+
+#[test]
+pub fn dedup_queue_drops_buffered_duplicates() {
+    let queue = DedupQueue::<usize>::new_arc();
+    let reader = queue.clone();
+
+    let writer_handle = queue.register_writer().unwrap();
+
+    assert_eq!(queue.send(writer_handle, 1), Ok(false));
+    assert_eq!(queue.send(writer_handle, 2), Ok(false));
+    assert_eq!(queue.send(writer_handle, 1), Ok(true));
+    assert_eq!(queue.send(writer_handle, 3), Ok(false));
+    assert_eq!(queue.send(writer_handle, 2), Ok(true));
+
+    assert_eq!(queue.read(), Ok(1));
+    assert_eq!(queue.send(writer_handle, 1), Ok(false));
+
+    assert_eq!(queue.deregister_writer(writer_handle), Ok(()));
+
+    let reader_handle = std::thread::spawn(move || {
+        let mut messages = vec![];
+
+        loop {
+            match reader.read() {
+                Ok(msg) => messages.push(msg),
+                Err(e) if e.is_terminal() => return Ok(messages),
+                Err(e) => return Err(e)
+            }
+        }
+    });
+
+    let result = reader_handle.join();
+
+    assert!(result.is_ok());
+    assert_eq!(result.unwrap(), Ok(vec![2, 3, 1]));
+}
+
+#[test]
+pub fn poison_pill_closes_queue() {
+    let queue = AsyncMsgQueue::<i64>::new().with_poison_pill(-1);
+    let writer_handle = queue.register_writer().unwrap();
+
+    for n in [1, 2, -1, 3] {
+        assert_eq!(queue.send(writer_handle, n), Ok(()));
+    }
+
+    assert_eq!(queue.read(), Ok(1));
+    assert_eq!(queue.is_closed(), Ok(false));
+    assert_eq!(queue.read(), Ok(2));
+    assert_eq!(queue.read(), Ok(3));
+    assert_eq!(queue.is_closed(), Ok(true));
+
+    assert_eq!(queue.send(writer_handle, 4), Err(QueueClosed));
+    assert_eq!(queue.read(), Err(EndOfTransmission));
+}
+
+#[test]
+pub fn fifo_and_lifo_ordering() {
+    for (ordering, expected) in [
+        (ReadOrder::Fifo, [1, 2, 3, 4]),
+        (ReadOrder::Lifo, [2, 4, 3, 1]),
+    ] {
+        let queue = AsyncMsgQueue::new().with_ordering(ordering);
+        let writer_handle = queue.register_writer().unwrap();
+
+        assert_eq!(queue.send(writer_handle, 1), Ok(()));
+        assert_eq!(queue.send(writer_handle, 2), Ok(()));
+        let first = queue.read().unwrap();
+        assert_eq!(queue.send(writer_handle, 3), Ok(()));
+        assert_eq!(queue.send(writer_handle, 4), Ok(()));
+        assert_eq!(queue.deregister_writer(writer_handle), Ok(()));
+
+        let mut received = vec![first];
+        while let Ok(msg) = queue.read() {
+            received.push(msg)
+        }
+
+        assert_eq!(received, expected);
+    }
+}
+
+#[test]
+pub fn send_seq_assigns_increasing_numbers() {
+    let queue = AsyncMsgQueue::new();
+    let writer_handle = queue.register_writer().unwrap();
+
+    assert_eq!(queue.send_seq(writer_handle, 'a'), Ok(0));
+    assert_eq!(queue.send(writer_handle, 'b'), Ok(()));
+    assert_eq!(queue.send_front(writer_handle, 'z'), Ok(()));
+    assert_eq!(queue.send_seq(writer_handle, 'c'), Ok(2));
+}
+
+#[test]
+pub fn flush_waits_for_buffer_to_drain() {
+    let queue = AsyncMsgQueue::new_arc();
+    let writer_handle = queue.register_writer().unwrap();
+
+    assert_eq!(queue.flush(), Ok(()));
+
+    for n in 0..10 {
+        assert_eq!(queue.send(writer_handle, n), Ok(()));
+    }
+
+    let reader = {
+        let queue = queue.clone();
+        std::thread::spawn(move || {
+            for _ in 0..10 {
+                std::thread::sleep(Duration::from_millis(1));
+                queue.read().unwrap();
+            }
+        })
+    };
+
+    assert_eq!(queue.flush(), Ok(()));
+    assert_eq!(queue.read_count(), 10);
+    assert_eq!(queue.is_closed(), Ok(false));
+
+    reader.join().unwrap();
+}
+
+#[test]
+pub fn flush_reports_termination_only_if_its_messages_were_lost() {
+    let queue = AsyncMsgQueue::new_arc();
+    let writer_handle = queue.register_writer().unwrap();
+
+    assert_eq!(queue.send(writer_handle, 1), Ok(()));
+
+    let flush = {
+        let queue = queue.clone();
+        std::thread::spawn(move || queue.flush())
+    };
+
+    std::thread::sleep(Duration::from_millis(10));
+
+    assert_eq!(queue.read(), Ok(1));
+    assert_eq!(queue.deregister_writer(writer_handle), Ok(()));
+    assert_eq!(queue.reopen(), Ok(()));
+
+    let writer_handle = queue.register_writer().unwrap();
+    assert_eq!(queue.send(writer_handle, 2), Ok(()));
+    assert_eq!(queue.force_terminate(), Ok(()));
+
+    assert_eq!(flush.join().unwrap(), Ok(()));
+
+    let queue = AsyncMsgQueue::new_arc();
+    let writer_handle = queue.register_writer().unwrap();
+
+    assert_eq!(queue.send(writer_handle, 1), Ok(()));
+
+    let flush = {
+        let queue = queue.clone();
+        std::thread::spawn(move || queue.flush())
+    };
+
+    std::thread::sleep(Duration::from_millis(10));
+
+    assert_eq!(queue.force_terminate(), Ok(()));
+    assert_eq!(flush.join().unwrap(), Err(QueueTerminated));
+}
+
+#[test]
+pub fn try_state_and_len() {
+    let queue = AsyncMsgQueue::new();
+    let writer_handle = queue.register_writer().unwrap();
+
+    assert_eq!(queue.try_state(), Some(QueueState::Open));
+    assert_eq!(queue.try_len(), Some(0));
+
+    assert_eq!(queue.send(writer_handle, 1), Ok(()));
+    assert_eq!(queue.try_len(), Some(1));
+
+    {
+        let _lock = queue.queue.lock().unwrap();
+        assert_eq!(queue.try_len(), None);
+    }
+
+    assert_eq!(queue.deregister_writer(writer_handle), Ok(()));
+    assert_eq!(queue.try_state(), Some(QueueState::Closed));
+}
+
+#[test]
+pub fn with_writer_deregisters_afterwards() {
+    let queue = AsyncMsgQueue::new_arc();
+
+    let sent = queue.with_writer(|writer_handle| {
+        (0..3).filter(|&n| queue.send(writer_handle, n).is_ok()).count()
+    });
+
+    assert_eq!(sent, Ok(3));
+    assert_eq!(queue.is_closed(), Ok(true));
+    assert_eq!(queue.fold(0, |a, b| a + b), Ok(3));
+
+    let queue = AsyncMsgQueue::<i32>::new_arc();
+    let result = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| {
+        queue.with_writer(|_| panic!("producer failed"))
+    }));
+
+    assert!(result.is_err());
+    assert_eq!(queue.is_closed(), Ok(true));
+}
+
+#[test]
+pub fn drain_into_reuses_buffer() {
+    let queue = AsyncMsgQueue::new();
+    let writer_handle = queue.register_writer().unwrap();
+    let mut buf = Vec::with_capacity(8);
+
+    assert_eq!(queue.drain_into(&mut buf), Ok(0));
+
+    for n in 0..3 {
+        assert_eq!(queue.send(writer_handle, n), Ok(()));
+    }
+
+    assert_eq!(queue.drain_into(&mut buf), Ok(3));
+    assert_eq!(buf, [0, 1, 2]);
+
+    assert_eq!(queue.send(writer_handle, 3), Ok(()));
+    assert_eq!(queue.deregister_writer(writer_handle), Ok(()));
+
+    assert_eq!(queue.drain_into(&mut buf), Ok(1));
+    assert_eq!(buf, [0, 1, 2, 3]);
+    assert_eq!(queue.drain_into(&mut buf), Err(EndOfTransmission));
+}
+
+#[test]
+pub fn pipe_to_forwards_messages() {
+    let source = AsyncMsgQueue::new_arc();
+    let dest = AsyncMsgQueue::new_arc();
+    let writer_handle = source.register_writer().unwrap();
+
+    let forwarder = source.pipe_to(&dest);
+
+    for n in 0..5 {
+        assert_eq!(source.send(writer_handle, n), Ok(()));
+    }
+
+    assert_eq!(source.deregister_writer(writer_handle), Ok(()));
+    assert_eq!(forwarder.join().unwrap(), Ok(()));
+
+    assert_eq!(dest.is_closed(), Ok(true));
+    assert_eq!(Arc::try_unwrap(dest).ok().unwrap().into_vec(), [0, 1, 2, 3, 4]);
+}
+
+#[test]
+pub fn local_queue_lifecycle() {
+    let queue = LocalMsgQueue::new();
+    let writer_handle = queue.register_writer().unwrap();
+
+    assert_eq!(queue.read(), Err(NoMessages));
+
+    assert_eq!(queue.send(writer_handle, 1), Ok(()));
+    assert_eq!(queue.send(writer_handle, 2), Ok(()));
+    assert_eq!(queue.deregister_writer(writer_handle), Ok(()));
+    assert_eq!(queue.send(writer_handle, 3), Err(UnknownWriter));
+
+    assert_eq!(queue.read(), Ok(1));
+    assert_eq!(queue.read(), Ok(2));
+    assert!(!queue.is_terminated());
+    assert_eq!(queue.read(), Err(EndOfTransmission));
+    assert_eq!(queue.read(), Err(QueueTerminated));
+}
+
+#[test]
+pub fn read_deadline_times_out() {
+    let queue = AsyncMsgQueue::new_arc();
+    let writer_handle = queue.register_writer().unwrap();
+
+    let deadline = Instant::now() + Duration::from_millis(20);
+    assert_eq!(queue.read_deadline(deadline), Err(Timeout));
+    assert!(Instant::now() >= deadline);
+
+    let writer = {
+        let queue = queue.clone();
+        std::thread::spawn(move || {
+            std::thread::sleep(Duration::from_millis(10));
+            queue.send(writer_handle, 1)
+        })
+    };
+
+    assert_eq!(queue.read_deadline(Instant::now() + Duration::from_secs(5)), Ok(1));
+    assert_eq!(writer.join().unwrap(), Ok(()));
+}
+
+#[test]
+pub fn is_writer_tracks_registration() {
+    let queue = AsyncMsgQueue::<()>::new();
+    let writer_handle = queue.register_writer().unwrap();
+
+    assert_eq!(queue.is_writer(writer_handle), Ok(true));
+    assert_eq!(queue.is_writer(writer_handle.wrapping_add(1)), Ok(false));
+
+    assert_eq!(queue.deregister_writer(writer_handle), Ok(()));
+    assert_eq!(queue.is_writer(writer_handle), Ok(false));
+}
+
+#[test]
+pub fn send_racing_deregister_never_enqueues_after_close() {
+    for _ in 0..100 {
+        let queue = AsyncMsgQueue::new_arc();
+        let writer_handle = queue.register_writer().unwrap();
+
+        let sender = {
+            let queue = queue.clone();
+            std::thread::spawn(move || {
+                let mut sent = 0;
+                while queue.send(writer_handle, sent).is_ok() {
+                    sent += 1
+                }
+                sent
+            })
+        };
+
+        std::thread::yield_now();
+        assert_eq!(queue.deregister_writer(writer_handle), Ok(()));
+        let closed_len = queue.queue.lock().unwrap().len();
+
+        let sent = sender.join().unwrap();
+
+        assert_eq!(queue.queue.lock().unwrap().len(), closed_len);
+        assert_eq!(closed_len, sent);
+    }
+}
+
+#[test]
+pub fn lock_helpers_report_poisoned_locks() {
+    let queue = AsyncMsgQueue::<i32>::new_arc();
+    let writer_handle = queue.register_writer().unwrap();
+
+    assert_eq!(queue.lock_queue().map(|lock| lock.len()), Ok(0));
+    assert_eq!(queue.lock_state().map(|state| *state), Ok(QueueState::Open));
+    assert_eq!(queue.lock_writers().map(|writers| writers.clone()), Ok(vec![writer_handle]));
+
+    let poisoner = queue.clone();
+    let _ = std::thread::spawn(move || {
+        let _lock = poisoner.queue.lock().unwrap();
+        panic!("poison the queue lock");
+    }).join();
+
+    assert!(queue.lock_queue().is_err_and(|e| e == NoLock));
+    assert_eq!(queue.send(writer_handle, 1), Err(NoLock));
+    assert_eq!(queue.lock_state().map(|state| *state), Ok(QueueState::Open));
+}
+
+#[test]
+pub fn read_or_status_reports_remaining_writers() {
+    let queue = AsyncMsgQueue::new();
+    let first_writer = queue.register_writer().unwrap();
+    let second_writer = queue.register_writer().unwrap();
+
+    assert_eq!(queue.read_or_status(), Err(ReadStatus::WouldBlock { writers_remaining: 2 }));
+
+    assert_eq!(queue.send(first_writer, 1), Ok(()));
+    assert_eq!(queue.deregister_writer(first_writer), Ok(()));
+
+    assert_eq!(queue.read_or_status(), Ok(1));
+    assert_eq!(queue.read_or_status(), Err(ReadStatus::WouldBlock { writers_remaining: 1 }));
+
+    assert_eq!(queue.deregister_writer(second_writer), Ok(()));
+    assert_eq!(queue.read_or_status(), Err(ReadStatus::Ended));
+    assert_eq!(queue.read_or_status(), Err(ReadStatus::Ended));
+}
+
+#[test]
+pub fn shrink_to_fit_releases_memory() {
+    let queue = AsyncMsgQueue::<u64>::new();
+    let writer_handle = queue.register_writer().unwrap();
+
+    for n in 0..1000 {
+        assert_eq!(queue.send(writer_handle, n), Ok(()));
+    }
+
+    let burst_bytes = queue.allocated_bytes().unwrap();
+    assert!(burst_bytes >= 1000 * std::mem::size_of::<u64>());
+
+    for n in 0..990 {
+        assert_eq!(queue.read(), Ok(n));
+    }
+
+    assert_eq!(queue.allocated_bytes(), Ok(burst_bytes));
+    assert_eq!(queue.shrink_to_fit(), Ok(()));
+    assert!(queue.allocated_bytes().unwrap() < burst_bytes);
+
+    assert_eq!(queue.read(), Ok(990));
+}
+
+#[test]
+pub fn router_fans_out_by_selector() {
+    let source = AsyncMsgQueue::new_arc();
+    let writer_handle = source.register_writer().unwrap();
+
+    let router = Router::new(&source, 3, |n: &usize| n % 3).unwrap();
+
+    for n in 0..9 {
+        assert_eq!(source.send(writer_handle, n), Ok(()));
+    }
+
+    assert_eq!(source.deregister_writer(writer_handle), Ok(()));
+
+    for i in 0..3 {
+        let channel = router.channel(i).unwrap();
+        assert_eq!(channel.fold(vec![], |mut v, n| { v.push(n); v }), Ok(vec![i, i + 3, i + 6]));
+        assert_eq!(channel.is_terminated(), Ok(true));
+    }
+
+    assert!(router.channel(3).is_none());
+    assert_eq!(router.join().unwrap(), Ok(()));
+}
+
+#[test]
+pub fn wake_strategies_deliver_to_parked_readers() {
+    for strategy in [WakeStrategy::One, WakeStrategy::All] {
+        let queue = Arc::new(AsyncMsgQueue::new()
+            .with_read_strategy(ReadStrategy::Park)
+            .with_wake_strategy(strategy));
+
+        let writer_handle = queue.register_writer().unwrap();
+
+        let readers: Vec<_> = (0..4).map(|_| {
+            let queue = queue.clone();
+            std::thread::spawn(move || queue.fold(0, |count, _: i32| count + 1))
+        }).collect();
+
+        for n in 0..100 {
+            assert_eq!(queue.send(writer_handle, n), Ok(()));
+        }
+
+        assert_eq!(queue.deregister_writer(writer_handle), Ok(()));
+
+        let received: usize = readers.into_iter()
+            .map(|reader| reader.join().unwrap().unwrap())
+            .sum();
+
+        assert_eq!(received, 100);
+    }
+}
+
+#[test]
+pub fn send_if_checks_buffer_under_lock() {
+    let queue = AsyncMsgQueue::new();
+    let writer_handle = queue.register_writer().unwrap();
+
+    let not_repeat = |n: i32| move |buffered: &mut dyn Iterator<Item = &i32>| buffered.last() != Some(&n);
+
+    assert_eq!(queue.send_if(writer_handle, 1, not_repeat(1)), Ok(true));
+    assert_eq!(queue.send_if(writer_handle, 1, not_repeat(1)), Ok(false));
+    assert_eq!(queue.send_if(writer_handle, 2, not_repeat(2)), Ok(true));
+    assert_eq!(queue.send_if(writer_handle, 3, |buffered| buffered.count() < 2), Ok(false));
+
+    assert_eq!(queue.read(), Ok(1));
+    assert_eq!(queue.read(), Ok(2));
+
+    assert_eq!(queue.deregister_writer(writer_handle), Ok(()));
+    assert_eq!(queue.send_if(writer_handle, 4, |_| true), Err(UnknownWriter));
+}
+
+#[cfg(feature = "metrics")]
+#[test]
+pub fn lock_contention_is_counted() {
+    let queue = AsyncMsgQueue::<()>::new_arc();
+    assert_eq!(queue.lock_contention_count(), 0);
+
+    let lock = queue.queue.lock().unwrap();
+
+    let waiter = {
+        let queue = queue.clone();
+        std::thread::spawn(move || queue.epoch())
+    };
+
+    while queue.lock_contention_count() == 0 {
+        std::thread::yield_now();
+    }
+
+    drop(lock);
+
+    assert_eq!(waiter.join().unwrap(), Ok(0));
+    assert_eq!(queue.lock_contention_count(), 1);
+}
+
+#[test]
+pub fn sharded_queue_delivers_every_message() {
+    let queue = ShardedMsgQueue::new_arc(4);
+    assert_eq!(queue.shard_count(), 4);
+
+    let writer_handles: Vec<_> = (0..4).map(|_| queue.register_writer().unwrap()).collect();
+
+    let writers: Vec<_> = writer_handles.into_iter().zip(0..).map(|(writer_handle, w)| {
+        let queue = queue.clone();
+        std::thread::spawn(move || {
+            for n in 0..250 {
+                queue.send(writer_handle, w * 250 + n).unwrap();
+            }
+            queue.deregister_writer(writer_handle).unwrap();
+        })
+    }).collect();
+
+    let mut received = vec![];
+    while let Ok(msg) = queue.read() {
+        received.push(msg)
+    }
+
+    for writer in writers {
+        writer.join().unwrap();
+    }
+
+    received.sort();
+    assert_eq!(received, (0..1000).collect::<Vec<_>>());
+    assert_eq!(queue.read(), Err(QueueTerminated));
+}
+
+#[test]
+pub fn writer_guard_counts_and_deregisters() {
+    let queue = AsyncMsgQueue::new();
+
+    {
+        let writer = queue.register_writer_guard().unwrap().with_name("producer");
+
+        assert_eq!(writer.name(), Some("producer"));
+        assert_eq!(queue.is_writer(writer.id()), Ok(true));
+
+        assert_eq!(writer.send(1), Ok(()));
+        assert_eq!(writer.send_all([2, 3]), Ok(()));
+        assert_eq!(writer.sent(), 3);
+    }
+
+    assert_eq!(queue.is_closed(), Ok(true));
+    assert_eq!(queue.fold(0, |a, b| a + b), Ok(6));
+}
+
+#[test]
+pub fn read_until_returns_when_flag_set() {
+    let queue = AsyncMsgQueue::<i32>::new_arc();
+    let writer_handle = queue.register_writer().unwrap();
+    let cancel = Arc::new(std::sync::atomic::AtomicBool::new(false));
+
+    assert_eq!(queue.send(writer_handle, 1), Ok(()));
+    assert_eq!(queue.read_until(&cancel), Ok(1));
+
+    let reader = {
+        let (queue, cancel) = (queue.clone(), cancel.clone());
+        std::thread::spawn(move || queue.read_until(&cancel))
+    };
+
+    std::thread::sleep(Duration::from_millis(20));
+    cancel.store(true, std::sync::atomic::Ordering::Relaxed);
+
+    assert_eq!(reader.join().unwrap(), Err(Cancelled));
+    assert_eq!(queue.is_closed(), Ok(false));
+}
+
+/// Runs a future to completion on the current thread
+#[cfg(feature = "async")]
+fn block_on<F: std::future::Future>(future: F) -> F::Output {
+    struct ThreadWaker(std::thread::Thread);
+
+    impl std::task::Wake for ThreadWaker {
+        fn wake(self: Arc<Self>) { self.0.unpark() }
+    }
+
+    let waker = Arc::new(ThreadWaker(std::thread::current())).into();
+    let mut cx = std::task::Context::from_waker(&waker);
+    let mut future = std::pin::pin!(future);
+
+    loop {
+        match future.as_mut().poll(&mut cx) {
+            std::task::Poll::Ready(output) => return output,
+            std::task::Poll::Pending => std::thread::park(),
+        }
+    }
+}
+
+#[cfg(feature = "async")]
+#[test]
+pub fn closed_and_terminated_futures_resolve() {
+    let queue = AsyncMsgQueue::new_arc();
+    let writer_handle = queue.register_writer().unwrap();
+
+    assert_eq!(queue.send(writer_handle, 1), Ok(()));
+
+    let closer = {
+        let queue = queue.clone();
+        std::thread::spawn(move || {
+            std::thread::sleep(Duration::from_millis(10));
+            queue.deregister_writer(writer_handle)
+        })
+    };
+
+    block_on(queue.closed());
+    assert_eq!(queue.is_closed(), Ok(true));
+    assert_eq!(closer.join().unwrap(), Ok(()));
+
+    let reader = {
+        let queue = queue.clone();
+        std::thread::spawn(move || queue.fold(0, |a, b| a + b))
+    };
+
+    block_on(queue.terminated());
+    assert_eq!(queue.is_terminated(), Ok(true));
+    assert_eq!(reader.join().unwrap(), Ok(1));
+
+    block_on(queue.closed());
+}
+
+#[test]
+pub fn len_by_priority_has_default_entry() {
+    let queue = AsyncMsgQueue::new();
+    let writer_handle = queue.register_writer().unwrap();
+
+    assert_eq!(queue.len_by_priority(), Ok(BTreeMap::from([(DEFAULT_PRIORITY, 0)])));
+
+    assert_eq!(queue.send(writer_handle, 'a'), Ok(()));
+    assert_eq!(queue.send(writer_handle, 'b'), Ok(()));
+
+    assert_eq!(queue.len_by_priority(), Ok(BTreeMap::from([(DEFAULT_PRIORITY, 2)])));
+}
+
+#[test]
+pub fn close_reason_records_cause() {
+    let queue = AsyncMsgQueue::<i32>::new();
+    let first_writer = queue.register_writer().unwrap();
+    let second_writer = queue.register_writer().unwrap();
+
+    assert_eq!(queue.close_reason(), Ok(None));
+
+    assert_eq!(queue.deregister_writer(first_writer), Ok(()));
+    assert_eq!(queue.close_reason(), Ok(None));
+    assert_eq!(queue.deregister_writer(second_writer), Ok(()));
+    assert_eq!(queue.close_reason(), Ok(Some(CloseReason::LastWriterDeregistered)));
+
+    let queue = AsyncMsgQueue::new().with_poison_pill(0);
+    let writer_handle = queue.register_writer().unwrap();
+
+    assert_eq!(queue.send(writer_handle, 0), Ok(()));
+    assert_eq!(queue.read(), Err(EndOfTransmission));
+
+    assert_eq!(queue.send(writer_handle, 1), Err(QueueClosed));
+    assert_eq!(queue.close_reason(), Ok(Some(CloseReason::PoisonPill)));
+
+    assert_eq!(queue.force_terminate(), Ok(()));
+    assert_eq!(queue.close_reason(), Ok(Some(CloseReason::PoisonPill)));
+
+    let queue = AsyncMsgQueue::<i32>::new();
+    let _writer_handle = queue.register_writer().unwrap();
+
+    assert_eq!(queue.force_terminate(), Ok(()));
+    assert_eq!(queue.close_reason(), Ok(Some(CloseReason::Terminated)));
+}
+
+#[test]
+pub fn poison_pills_are_hidden_from_inspection() {
+    let queue = AsyncMsgQueue::<i64>::new().with_poison_pill(-1);
+    let writer_handle = queue.register_writer().unwrap();
+
+    for n in [1, -1, 2, 3] {
+        assert_eq!(queue.send(writer_handle, n), Ok(()));
+    }
+
+    let no_pills = |buffered: &mut dyn Iterator<Item = &i64>| buffered.min() == Some(&1);
+
+    assert_eq!(queue.send_if(writer_handle, 4, no_pills), Ok(true));
+    assert_eq!(queue.extract(|&n| n < 2), Ok(vec![1]));
+    assert_eq!(queue.into_vec(), vec![2, 3, 4]);
+}
+
+#[test]
+pub fn writer_guard_extends_queue() {
+    let queue = AsyncMsgQueue::new();
+
+    {
+        let mut writer = queue.register_writer_guard().unwrap();
+        writer.extend(vec![1, 2]);
+        writer.extend(3..5);
+        assert_eq!(writer.sent(), 4);
+    }
+
+    assert_eq!(queue.fold(vec![], |mut v, n| { v.push(n); v }), Ok(vec![1, 2, 3, 4]));
+}
+
+#[test]
+pub fn reserve_allocates_ahead_of_sends() {
+    let queue = AsyncMsgQueue::<u64>::new().with_capacity(4);
+    let writer = queue.register_writer_guard().unwrap();
+
+    assert_eq!(writer.reserve(100), Ok(()));
+    assert!(queue.allocated_bytes().unwrap() >= 100 * std::mem::size_of::<u64>());
+
+    assert_eq!(writer.send_all(0..4), Ok(()));
+    assert_eq!(writer.send(4), Err(QueueFull));
+}
+
+#[test]
+pub fn for_each_timeout_detects_stuck_writer() {
+    let queue = AsyncMsgQueue::new();
+    let writer_handle = queue.register_writer().unwrap();
+
+    for n in 0..3 {
+        assert_eq!(queue.send(writer_handle, n), Ok(()));
+    }
+
+    let mut received = vec![];
+    assert_eq!(queue.for_each_timeout(Duration::from_millis(10), |n| received.push(n)), Err(Timeout));
+    assert_eq!(received, [0, 1, 2]);
+
+    assert_eq!(queue.send(writer_handle, 3), Ok(()));
+    assert_eq!(queue.deregister_writer(writer_handle), Ok(()));
+
+    assert_eq!(queue.for_each_timeout(Duration::from_millis(10), |n| received.push(n)), Ok(()));
+    assert_eq!(received, [0, 1, 2, 3]);
+}
+
+#[test]
+pub fn accepted_messages_are_read_before_termination() {
+    for _ in 0..20 {
+        let queue = Arc::new(AsyncMsgQueue::new().with_read_strategy(ReadStrategy::Park));
+        let writer_handles: Vec<_> = (0..4).map(|_| queue.register_writer().unwrap()).collect();
+
+        let readers: Vec<_> = (0..3).map(|_| {
+            let queue = queue.clone();
+            std::thread::spawn(move || queue.fold(0, |count, _: usize| count + 1))
+        }).collect();
+
+        let writers: Vec<_> = writer_handles.into_iter().map(|writer_handle| {
+            let queue = queue.clone();
+            std::thread::spawn(move || {
+                let accepted = (0..200).filter(|&n| queue.send(writer_handle, n).is_ok()).count();
+                queue.deregister_writer(writer_handle).unwrap();
+                accepted
+            })
+        }).collect();
+
+        let accepted: usize = writers.into_iter().map(|writer| writer.join().unwrap()).sum();
+        let read: usize = readers.into_iter().map(|reader| reader.join().unwrap().unwrap()).sum();
+
+        assert_eq!(read, accepted);
+        assert_eq!(queue.is_terminated(), Ok(true));
+    }
+}
+
+#[test]
+pub fn send_timeout_returns_message_when_full() {
+    let queue = Arc::new(AsyncMsgQueue::new().with_capacity_policy(1, OverflowPolicy::Block));
+    let writer_handle = queue.register_writer().unwrap();
+
+    assert_eq!(queue.send(writer_handle, 1), Ok(()));
+    assert_eq!(queue.send_timeout(writer_handle, 2, Duration::from_millis(10)), Err((Timeout, 2)));
+
+    let reader = {
+        let queue = queue.clone();
+        std::thread::spawn(move || {
+            std::thread::sleep(Duration::from_millis(10));
+            queue.read()
+        })
+    };
+
+    assert_eq!(queue.send_timeout(writer_handle, 2, Duration::from_secs(5)), Ok(()));
+    assert_eq!(reader.join().unwrap(), Ok(1));
+
+    let second_writer = queue.register_writer().unwrap();
+    let closer = {
+        let queue = queue.clone();
+        std::thread::spawn(move || {
+            std::thread::sleep(Duration::from_millis(10));
+            queue.deregister_writer(writer_handle).unwrap();
+            queue.deregister_writer(second_writer)
+        })
+    };
+
+    assert_eq!(queue.send_timeout(second_writer, 3, Duration::from_secs(5)), Err((QueueClosed, 3)));
+    assert_eq!(closer.join().unwrap(), Ok(()));
+}
+
+#[test]
+pub fn send_timeout_waits_whatever_the_overflow_policy() {
+    let queue = AsyncMsgQueue::new_bounded_arc(1);
+    let writer_handle = queue.register_writer().unwrap();
+
+    assert_eq!(queue.send(writer_handle, 1), Ok(()));
+    assert_eq!(queue.send(writer_handle, 2), Err(QueueFull));
+
+    let reader = {
+        let queue = queue.clone();
+        std::thread::spawn(move || {
+            std::thread::sleep(Duration::from_millis(10));
+            queue.read()
+        })
+    };
+
+    assert_eq!(queue.send_timeout(writer_handle, 2, Duration::from_secs(5)), Ok(()));
+    assert_eq!(reader.join().unwrap(), Ok(1));
+    assert_eq!(queue.read(), Ok(2));
+}
+
+#[test]
+pub fn min_readers_delays_termination() {
+    let queue = Arc::new(AsyncMsgQueue::<i32>::new()
+        .with_read_strategy(ReadStrategy::Park)
+        .with_min_readers(0));
+
+    let writer_handle = queue.register_writer().unwrap();
+    let first_reader = queue.register_reader().unwrap();
+    let second_reader = queue.register_reader().unwrap();
+
+    assert_eq!(queue.deregister_writer(writer_handle), Ok(()));
+    assert_eq!(queue.try_read_batch(1), Ok(vec![]));
+    assert_eq!(queue.peek(), Err(NoMessages));
+    assert_eq!(queue.is_terminated(), Ok(false));
+
+    let reader = {
+        let queue = queue.clone();
+        std::thread::spawn(move || queue.read_as(first_reader))
+    };
+
+    std::thread::sleep(Duration::from_millis(10));
+    assert_eq!(queue.is_terminated(), Ok(false));
+
+    assert_eq!(queue.deregister_reader(second_reader), Ok(()));
+    assert_eq!(reader.join().unwrap(), Err(EndOfTransmission));
+    assert_eq!(queue.is_terminated(), Ok(true));
+}
+
+#[test]
+pub fn min_readers_does_not_wait_for_the_reading_reader() {
+    let queue = AsyncMsgQueue::<i32>::new().with_min_readers(0);
+
+    let writer_handle = queue.register_writer().unwrap();
+    let reader = queue.register_reader().unwrap();
+
+    assert_eq!(queue.send(writer_handle, 1), Ok(()));
+    assert_eq!(queue.deregister_writer(writer_handle), Ok(()));
+
+    assert_eq!(queue.read_as(reader), Ok(1));
+    assert_eq!(queue.read_as(reader), Err(EndOfTransmission));
+    assert_eq!(queue.is_terminated(), Ok(true));
+}
+
+#[test]
+pub fn backpressure_hook_reports_wait() {
+    let waits = Arc::new(Mutex::new(vec![]));
+
+    let queue = {
+        let waits = waits.clone();
+        Arc::new(AsyncMsgQueue::new()
+            .with_capacity_policy(1, OverflowPolicy::Block)
+            .on_backpressure(move |waited| waits.lock().unwrap().push(waited)))
+    };
+
+    let writer_handle = queue.register_writer().unwrap();
+
+    assert_eq!(queue.send(writer_handle, 1), Ok(()));
+    assert!(waits.lock().unwrap().is_empty());
+
+    let reader = {
+        let queue = queue.clone();
+        std::thread::spawn(move || {
+            std::thread::sleep(Duration::from_millis(20));
+            queue.read()
+        })
+    };
+
+    assert_eq!(queue.send(writer_handle, 2), Ok(()));
+    assert_eq!(reader.join().unwrap(), Ok(1));
+
+    let waits = waits.lock().unwrap();
+    assert_eq!(waits.len(), 1);
+    assert!(waits[0] >= Duration::from_millis(10));
+}
+
+#[test]
+pub fn coalesce_queue_merges_within_window() {
+    let queue = CoalesceQueue::new(Duration::from_millis(20));
+    let writer_handle = queue.register_writer().unwrap();
+
+    assert_eq!(queue.send_coalesced(writer_handle, 'a', 1, |a, b| a + b), Ok(()));
+    assert_eq!(queue.send_coalesced(writer_handle, 'a', 2, |a, b| a + b), Ok(()));
+
+    std::thread::sleep(Duration::from_millis(30));
+    assert_eq!(queue.send_coalesced(writer_handle, 'a', 4, |a, b| a + b), Ok(()));
+
+    assert_eq!(queue.read(), Ok(('a', 3)));
+    assert_eq!(queue.read(), Ok(('a', 4)));
+}
+
+#[test]
+pub fn drain_guard_takes_buffer_exclusively() {
+    let queue = AsyncMsgQueue::new_arc();
+    let writer_handle = queue.register_writer().unwrap();
+
+    for n in 0..5 {
+        assert_eq!(queue.send(writer_handle, n), Ok(()));
+    }
+
+    let mut guard = queue.lock_for_drain().unwrap();
+
+    let writer = {
+        let queue = queue.clone();
+        std::thread::spawn(move || queue.send(writer_handle, 5))
+    };
+
+    std::thread::sleep(Duration::from_millis(10));
+    assert_eq!(guard.len(), 5);
+    assert_eq!(guard.pop(), Ok(0));
+    assert_eq!(guard.by_ref().collect::<Vec<_>>(), [1, 2, 3, 4]);
+    assert_eq!(guard.pop(), Err(NoMessages));
+    drop(guard);
+
+    assert_eq!(writer.join().unwrap(), Ok(()));
+    assert_eq!(queue.read(), Ok(5));
+}
+
+#[test]
+pub fn headers_travel_with_messages() {
+    let queue = AsyncMsgQueue::new();
+    let writer_handle = queue.register_writer().unwrap();
+
+    let headers = Headers::from([("trace-id".to_string(), "abc".to_string())]);
+
+    assert_eq!(queue.send_with_headers(writer_handle, 1, headers.clone()), Ok(()));
+    assert_eq!(queue.send(writer_handle, 2), Ok(()));
+    assert_eq!(queue.send_with_headers(writer_handle, 3, headers.clone()), Ok(()));
+
+    assert_eq!(queue.read_with_headers(), Ok((1, headers)));
+    assert_eq!(queue.read_with_headers(), Ok((2, Headers::new())));
+    assert_eq!(queue.read(), Ok(3));
+}
+
+#[test]
+pub fn zip_pairs_messages() {
+    let letters = AsyncMsgQueue::new_arc();
+    let numbers = AsyncMsgQueue::new_arc();
+    let letter_writer = letters.register_writer().unwrap();
+    let number_writer = numbers.register_writer().unwrap();
+
+    let (zipped, _) = AsyncMsgQueue::zip(&letters, &numbers).unwrap();
+
+    for (letter, number) in ['a', 'b', 'c'].into_iter().zip(0..) {
+        assert_eq!(letters.send(letter_writer, letter), Ok(()));
+        assert_eq!(numbers.send(number_writer, number), Ok(()));
+    }
+
+    assert_eq!(numbers.send(number_writer, 3), Ok(()));
+    assert_eq!(letters.deregister_writer(letter_writer), Ok(()));
+
+    let pairs = zipped.fold(vec![], |mut pairs, pair| { pairs.push(pair); pairs });
+    assert_eq!(pairs, Ok(vec![('a', 0), ('b', 1), ('c', 2)]));
+}
+
+#[test]
+pub fn try_read_status_reports_why_empty() {
+    let queue = AsyncMsgQueue::new().with_min_readers(0);
+    let writer_handle = queue.register_writer().unwrap();
+    let reader = queue.register_reader().unwrap();
+
+    assert_eq!(queue.try_read_status(), Err(EmptyStatus::OpenEmpty));
+
+    assert_eq!(queue.send(writer_handle, 1), Ok(()));
+    assert_eq!(queue.deregister_writer(writer_handle), Ok(()));
+
+    assert_eq!(queue.try_read_status(), Ok(1));
+    assert_eq!(queue.try_read_status(), Err(EmptyStatus::ClosedDraining));
+
+    assert_eq!(queue.deregister_reader(reader), Ok(()));
+    assert_eq!(queue.try_read_status(), Err(EmptyStatus::Terminated));
+    assert_eq!(queue.try_read_status(), Err(EmptyStatus::Terminated));
+}
+
+#[test]
+pub fn adaptive_capacity_grows_and_shrinks_its_target() {
+    let queue = AsyncMsgQueue::new().with_adaptive_capacity(2, 8);
+    let writer = queue.register_writer().unwrap();
+
+    assert_eq!(queue.capacity_target(), Some(2));
+
+    let mut sent = 0;
+
+    for target in [2, 4, 8] {
+        while queue.try_send(writer, sent).is_ok() { sent += 1 }
+
+        assert_eq!(queue.len(), Ok(target));
+
+        for _ in 2..CAPACITY_GROWTH_HITS {
+            assert_eq!(queue.try_send(writer, sent), Err(MsgQueueError::QueueFull));
+        }
+
+        assert_eq!(queue.capacity_target(), Some(target));
+    }
+
+    for _ in 0..CAPACITY_GROWTH_HITS { assert_eq!(queue.try_send(writer, sent), Err(MsgQueueError::QueueFull)) }
+    assert_eq!(queue.capacity_target(), Some(8));
+
+    for i in 0..8 { assert_eq!(queue.read(), Ok(i)) }
+    assert_eq!(queue.capacity_target(), Some(4));
+
+    assert_eq!(AsyncMsgQueue::<i32>::new().capacity_target(), None);
+}
+
+#[test]
+pub fn adaptive_capacity_blocks_at_its_target_before_growing() {
+    let queue = Arc::new(AsyncMsgQueue::new().with_adaptive_capacity(2, 8));
+    let writer = queue.register_writer().unwrap();
+
+    for i in 0..2 { assert_eq!(queue.send(writer, i), Ok(())) }
+
+    let sender = {
+        let queue = queue.clone();
+        std::thread::spawn(move || queue.send(writer, 2))
+    };
+
+    std::thread::sleep(Duration::from_millis(50));
+    assert!(!sender.is_finished());
+    assert_eq!(queue.len(), Ok(2));
+    assert_eq!(queue.capacity_target(), Some(2));
+
+    assert_eq!(queue.read(), Ok(0));
+    assert_eq!(sender.join().unwrap(), Ok(()));
+    assert_eq!(queue.capacity_target(), Some(2));
+}
+
+#[cfg(feature = "testing")]
+#[test]
+pub fn send_barrier_shows_close_waits_for_an_accepted_send() {
+    use std::sync::Barrier;
+
+    let queue = AsyncMsgQueue::new_arc();
+    let writer = queue.register_writer().unwrap();
+
+    let paused = Arc::new(Barrier::new(2));
+    let resume = Arc::new(Barrier::new(2));
+
+    let (hook_paused, hook_resume) = (paused.clone(), resume.clone());
+    queue.set_send_barrier(move || { hook_paused.wait(); hook_resume.wait(); }).unwrap();
+
+    let sender = queue.clone();
+    let send = std::thread::spawn(move || sender.send(writer, 1));
+
+    paused.wait();
+
+    let closer = queue.clone();
+    let close = std::thread::spawn(move || closer.deregister_writer(writer));
+
+    std::thread::sleep(Duration::from_millis(20));
+    assert!(!queue.is_closed().unwrap());
+
+    resume.wait();
+
+    assert_eq!(send.join().unwrap(), Ok(()));
+    assert_eq!(close.join().unwrap(), Ok(()));
+    assert_eq!(queue.read(), Ok(1));
+    assert_eq!(queue.read(), Err(MsgQueueError::EndOfTransmission));
+}
+
+#[cfg(feature = "testing")]
+#[test]
+pub fn send_barrier_runs_for_conditional_sends() {
+    let queue = AsyncMsgQueue::new();
+    let writer = queue.register_writer().unwrap();
+
+    let barriers = Arc::new(std::sync::atomic::AtomicUsize::new(0));
+    let counter = barriers.clone();
+    queue.set_send_barrier(move || { counter.fetch_add(1, std::sync::atomic::Ordering::Relaxed); }).unwrap();
+
+    assert_eq!(queue.send(writer, 1), Ok(()));
+    assert_eq!(queue.send_if(writer, 2, |_| true), Ok(true));
+    assert_eq!(queue.send_if(writer, 3, |_| false), Ok(false));
+
+    assert_eq!(barriers.load(std::sync::atomic::Ordering::Relaxed), 2);
+}
+
+#[cfg(feature = "async")]
+#[test]
+pub fn chunked_stream_yields_full_chunks_then_flushes() {
+    let queue = AsyncMsgQueue::new_arc();
+    let writer_handle = queue.register_writer().unwrap();
+
+    let writer = {
+        let queue = queue.clone();
+        std::thread::spawn(move || {
+            for i in 0..5 {
+                std::thread::sleep(Duration::from_millis(2));
+                queue.send(writer_handle, i)?;
+            }
+
+            queue.deregister_writer(writer_handle)
+        })
+    };
+
+    let mut stream = queue.into_chunked_stream(2);
+
+    assert_eq!(block_on(stream.next_chunk()), Some(vec![0, 1]));
+    assert_eq!(block_on(stream.next_chunk()), Some(vec![2, 3]));
+    assert_eq!(block_on(stream.next_chunk()), Some(vec![4]));
+    assert_eq!(block_on(stream.next_chunk()), None);
+    assert_eq!(writer.join().unwrap(), Ok(()));
+}
+
+#[test]
+pub fn send_validator_rejects_messages() {
+    let queue = AsyncMsgQueue::new().with_send_validator(|t: &i32| *t >= 0);
+    let writer_handle = queue.register_writer().unwrap();
+
+    assert_eq!(queue.send(writer_handle, 1), Ok(()));
+    assert_eq!(queue.send(writer_handle, -1), Err(MsgQueueError::Rejected));
+    assert_eq!(queue.send_timeout(writer_handle, -2, Duration::ZERO), Err((MsgQueueError::Rejected, -2)));
+    assert_eq!(queue.send_returning(writer_handle, -3), Err((MsgQueueError::Rejected, -3)));
+    assert_eq!(queue.send_if(writer_handle, -4, |_| true), Err(MsgQueueError::Rejected));
+    assert_eq!(queue.send(0, -1), Err(MsgQueueError::UnknownWriter));
+
+    assert_eq!(queue.try_read_batch(10), Ok(vec![1]));
+}
+
+#[test]
+pub fn await_close_returns_once_writers_are_gone() {
+    let queue = AsyncMsgQueue::new_arc();
+    let writer_handle = queue.register_writer().unwrap();
+
+    let closer = {
+        let queue = queue.clone();
+        std::thread::spawn(move || {
+            std::thread::sleep(Duration::from_millis(10));
+            queue.send(writer_handle, 1)?;
+            queue.deregister_writer(writer_handle)
+        })
+    };
+
+    assert_eq!(queue.await_close(), Ok(()));
+    assert_eq!(queue.is_closed(), Ok(true));
+    assert_eq!(closer.join().unwrap(), Ok(()));
+
+    assert_eq!(queue.read(), Ok(1));
+    assert_eq!(queue.await_close(), Ok(()));
+}
+
+#[test]
+pub fn throttle_spaces_out_messages() {
+    let queue = AsyncMsgQueue::new_arc();
+    let writer_handle = queue.register_writer().unwrap();
+
+    for i in 0..3 { assert_eq!(queue.send(writer_handle, i), Ok(())) }
+    assert_eq!(queue.deregister_writer(writer_handle), Ok(()));
+
+    let start = Instant::now();
+    let (throttled, _) = queue.throttle(Duration::from_millis(20)).unwrap();
+
+    assert_eq!(throttled.read(), Ok(0));
+    assert_eq!(throttled.read(), Ok(1));
+    assert_eq!(throttled.read(), Ok(2));
+    assert!(start.elapsed() >= Duration::from_millis(40));
+
+    assert_eq!(throttled.read(), Err(MsgQueueError::EndOfTransmission));
+}
+
+#[test]
+pub fn shared_log_hands_every_reader_the_same_message() {
+    struct Payload(Vec<u8>);
+
+    let log = SharedLogQueue::<Payload>::new();
+    let (first, second) = (log.register_reader().unwrap(), log.register_reader().unwrap());
+    let writer = log.register_writer().unwrap();
+
+    assert_eq!(log.send_shared(writer, Payload(vec![0; 1024])), Ok(()));
+
+    let (a, b) = (log.read(first).unwrap(), log.read(second).unwrap());
+
+    assert!(Arc::ptr_eq(&a, &b));
+    assert_eq!(a.0.len(), 1024);
+}
+
+#[test]
+pub fn consumer_groups_each_receive_every_message_once() {
+    let queue = ConsumerGroupQueue::<usize>::new_arc();
+
+    let readers: Vec<_> = ["a", "a", "b", "b"].into_iter()
+        .map(|group| (group, queue.join_group(group).unwrap()))
+        .collect();
+
+    let writer = queue.register_writer().unwrap();
+
+    let handles: Vec<_> = readers.into_iter()
+        .map(|(group, reader)| {
+            let queue = queue.clone();
+            std::thread::spawn(move || {
+                let mut messages = vec![];
+
+                loop {
+                    match queue.read(reader) {
+                        Ok(msg) => messages.push(msg),
+                        Err(e) if e.is_terminal() => return (group, messages),
+                        Err(e) => panic!("{}", e.to_string()),
+                    }
+                }
+            })
+        })
+        .collect();
+
+    for i in 0..100 { assert_eq!(queue.send(writer, i), Ok(())) }
+    assert_eq!(queue.deregister_writer(writer), Ok(()));
+
+    let mut by_group: HashMap<&str, Vec<usize>> = HashMap::new();
+
+    for handle in handles {
+        let (group, messages) = handle.join().unwrap();
+        by_group.entry(group).or_default().extend(messages);
+    }
+
+    for messages in by_group.values_mut() {
+        messages.sort();
+        assert_eq!(*messages, (0..100).collect::<Vec<_>>());
+    }
+
+    assert_eq!(queue.is_terminated(), Ok(true));
+}
+
+#[test]
+pub fn seq_gap_counts_missed_messages() {
+    assert_eq!(seq_gap(5, 6), 0);
+    assert_eq!(seq_gap(5, 9), 3);
+    assert_eq!(seq_gap(5, 5), 0);
+    assert_eq!(seq_gap(u64::MAX, 0), 0);
+    assert_eq!(seq_gap(u64::MAX - 1, 1), 2);
+
+    let queue = AsyncMsgQueue::new();
+    let writer_handle = queue.register_writer().unwrap();
+
+    let first = queue.send_seq(writer_handle, 'a').unwrap();
+    assert_eq!(queue.send_seq(writer_handle, 'b'), Ok(first + 1));
+    assert_eq!(queue.clear(), Ok(()));
+
+    let after_clear = queue.send_seq(writer_handle, 'c').unwrap();
+    assert_eq!(seq_gap(first, after_clear), 1);
+}
+
+#[test]
+pub fn subscribe_supervised_reports_consumer_panics() {
+    let queue = AsyncMsgQueue::new_arc();
+    let writer_handle = queue.register_writer().unwrap();
+
+    let (panics, panic_messages) = std::sync::mpsc::channel();
+
+    let handle = queue.subscribe_supervised(
+        |msg: i32| if msg == 2 { panic!("bad message {msg}") },
+        move |payload| panics.send(payload.downcast_ref::<String>().cloned()).unwrap(),
+    ).unwrap();
+
+    for i in 1..=3 { assert_eq!(queue.send(writer_handle, i), Ok(())) }
+
+    assert!(handle.join().is_err());
+    assert_eq!(panic_messages.recv(), Ok(Some("bad message 2".to_string())));
+    assert_eq!(queue.starved_readers(Duration::ZERO), Ok(vec![]));
+    assert_eq!(queue.read(), Ok(3));
+}
+
+#[test]
+pub fn subscribe_supervised_reports_panics_when_deregistering_fails() {
+    let queue = AsyncMsgQueue::new_arc();
+    let writer_handle = queue.register_writer().unwrap();
+
+    let (panics, panic_messages) = std::sync::mpsc::channel();
+    let registry = queue.clone();
+
+    let handle = queue.subscribe_supervised(
+        move |_: i32| {
+            registry.readers.lock().unwrap().clear();
+            panic!("bad message")
+        },
+        move |payload| panics.send(payload.downcast_ref::<&str>().copied()).unwrap(),
+    ).unwrap();
+
+    assert_eq!(queue.send(writer_handle, 1), Ok(()));
+
+    assert!(handle.join().is_err());
+    assert_eq!(panic_messages.recv(), Ok(Some("bad message")));
+}
+
+#[test]
+pub fn read_batch_timeout_waits_for_min_messages() {
+    let queue = AsyncMsgQueue::new_arc();
+    let writer_handle = queue.register_writer().unwrap();
+
+    assert_eq!(queue.read_batch_timeout(1, 4, Duration::from_millis(5)), Ok(vec![]));
+
+    let writer = {
+        let queue = queue.clone();
+        std::thread::spawn(move || {
+            for i in 0..6 {
+                std::thread::sleep(Duration::from_millis(2));
+                queue.send(writer_handle, i)?;
+            }
+
+            queue.deregister_writer(writer_handle)
+        })
+    };
+
+    let batch = queue.read_batch_timeout(3, 4, Duration::from_secs(5)).unwrap();
+    assert!((3..=4).contains(&batch.len()));
+    assert_eq!(batch, (0..batch.len()).collect::<Vec<_>>());
+
+    assert_eq!(writer.join().unwrap(), Ok(()));
+
+    let rest = queue.read_batch_timeout(10, 10, Duration::from_secs(5)).unwrap();
+    assert_eq!(rest, (batch.len()..6).collect::<Vec<_>>());
+    assert_eq!(queue.read_batch_timeout(1, 1, Duration::from_secs(5)), Err(MsgQueueError::EndOfTransmission));
+}
+
+#[test]
+pub fn metrics_receive_queue_events() {
+    #[derive(Default)]
+    struct Counts {
+        sent: AtomicUsize,
+        read: AtomicUsize,
+        closed: Mutex<Option<CloseReason>>,
+        terminated: AtomicBool,
+    }
+
+    impl QueueMetrics for Counts {
+        fn on_send(&self) { self.sent.fetch_add(1, std::sync::atomic::Ordering::Relaxed); }
+        fn on_read(&self) { self.read.fetch_add(1, std::sync::atomic::Ordering::Relaxed); }
+        fn on_close(&self, reason: CloseReason) { *self.closed.lock().unwrap() = Some(reason) }
+        fn on_terminate(&self) { self.terminated.store(true, std::sync::atomic::Ordering::Relaxed) }
+    }
+
+    let counts = Arc::new(Counts::default());
+    let queue = AsyncMsgQueue::new().with_metrics(counts.clone());
+    let writer_handle = queue.register_writer().unwrap();
+
+    for i in 0..3 { assert_eq!(queue.send(writer_handle, i), Ok(())) }
+    assert_eq!(queue.deregister_writer(writer_handle), Ok(()));
+    assert_eq!(queue.fold(0, |a, b| a + b), Ok(3));
+
+    assert_eq!(counts.sent.load(std::sync::atomic::Ordering::Relaxed), 3);
+    assert_eq!(counts.read.load(std::sync::atomic::Ordering::Relaxed), 3);
+    assert_eq!(*counts.closed.lock().unwrap(), Some(CloseReason::LastWriterDeregistered));
+    assert!(counts.terminated.load(std::sync::atomic::Ordering::Relaxed));
+}
+
+#[test]
+pub fn default_read_parks_until_sent_or_closed() {
+    let queue = AsyncMsgQueue::new_arc();
+    let writer_handle = queue.register_writer().unwrap();
+
+    let reader = {
+        let queue = queue.clone();
+        std::thread::spawn(move || (queue.read(), queue.read()))
+    };
+
+    std::thread::sleep(Duration::from_millis(10));
+    assert_eq!(queue.send(writer_handle, 1), Ok(()));
+
+    std::thread::sleep(Duration::from_millis(10));
+
+    assert_eq!(queue.deregister_writer(writer_handle), Ok(()));
+    assert_eq!(reader.join().unwrap(), (Ok(1), Err(MsgQueueError::EndOfTransmission)));
+}
+
+#[test]
+pub fn read_timeout_gives_up_without_messages() {
+    let queue = AsyncMsgQueue::<i32>::new_arc();
+    let writer_handle = queue.register_writer().unwrap();
+
+    let start = Instant::now();
+    assert_eq!(queue.read_timeout(Duration::from_millis(50)), Err(MsgQueueError::Timeout));
+    assert!(start.elapsed() >= Duration::from_millis(50));
+
+    let writer = {
+        let queue = queue.clone();
+        std::thread::spawn(move || {
+            std::thread::sleep(Duration::from_millis(10));
+            queue.send(writer_handle, 1)?;
+            queue.deregister_writer(writer_handle)
+        })
+    };
+
+    assert_eq!(queue.read_timeout(Duration::from_secs(5)), Ok(1));
+    assert_eq!(writer.join().unwrap(), Ok(()));
+    assert_eq!(queue.read_timeout(Duration::from_secs(5)), Err(MsgQueueError::EndOfTransmission));
+}
+
+#[test]
+pub fn try_read_never_blocks() {
+    let queue = AsyncMsgQueue::new();
+    let writer_handle = queue.register_writer().unwrap();
+
+    assert_eq!(queue.try_read(), Err(MsgQueueError::NoMessages));
+
+    for i in 0..3 {
+        assert_eq!(queue.send(writer_handle, i), Ok(()));
+        assert_eq!(queue.try_read(), Ok(i));
+        assert_eq!(queue.try_read(), Err(MsgQueueError::NoMessages));
+    }
+
+    assert_eq!(queue.is_terminated(), Ok(false));
+
+    assert_eq!(queue.send(writer_handle, 3), Ok(()));
+    assert_eq!(queue.deregister_writer(writer_handle), Ok(()));
+
+    assert_eq!(queue.try_read(), Ok(3));
+    assert_eq!(queue.try_read(), Err(MsgQueueError::EndOfTransmission));
+}
+
+#[test]
+pub fn peek_matches_next_read() {
+    let queue = AsyncMsgQueue::new();
+    let writer_handle = queue.register_writer().unwrap();
+
+    assert_eq!(queue.peek(), Err(MsgQueueError::NoMessages));
+
+    for i in 0..3 { assert_eq!(queue.send(writer_handle, i), Ok(())) }
+    assert_eq!(queue.send_front(writer_handle, 10), Ok(()));
+
+    for _ in 0..4 {
+        let peeked = queue.peek().unwrap();
+        assert_eq!(queue.peek(), Ok(peeked));
+        assert_eq!(queue.read(), Ok(peeked));
+    }
+
+    assert_eq!(queue.deregister_writer(writer_handle), Ok(()));
+    assert_eq!(queue.peek(), Err(MsgQueueError::EndOfTransmission));
+    assert_eq!(queue.read(), Err(MsgQueueError::EndOfTransmission));
+}
+
+#[test]
+pub fn large_queue_keeps_fifo_order() {
+    let queue = AsyncMsgQueue::new();
+    let writer_handle = queue.register_writer().unwrap();
+
+    for i in 0..100_000 { queue.send(writer_handle, i).unwrap() }
+
+    for i in 0..50_000 {
+        assert_eq!(queue.read(), Ok(i));
+        queue.send(writer_handle, 100_000 + i).unwrap();
+    }
+
+    assert_eq!(queue.len(), Ok(100_000));
+    assert_eq!(queue.into_vec(), (50_000..150_000).collect::<Vec<_>>());
+}
+
+/// Timing based, so it only runs when asked for, with `cargo test -- --ignored`
+#[test]
+#[ignore]
+pub fn sending_scales_linearly() {
+    fn time_to_fill(n: usize) -> Duration {
+        let queue = AsyncMsgQueue::new();
+        let writer_handle = queue.register_writer().unwrap();
+
+        let start = Instant::now();
+        for i in 0..n { queue.send(writer_handle, i).unwrap() }
+        start.elapsed()
+    }
+
+    let small = time_to_fill(10_000);
+    let large = time_to_fill(100_000);
+
+    // 10 times the messages should take about 10 times as long, rather than 100 times
+    assert!(large < small * 40, "{small:?} for 10k messages, {large:?} for 100k");
+}
+
+#[test]
+pub fn closing_twice_is_not_an_error() {
+    let queue = AsyncMsgQueue::<i32>::new();
+    let (first, second) = (queue.register_writer().unwrap(), queue.register_writer().unwrap());
+
+    assert_eq!(queue.deregister_writer(first), Ok(()));
+    assert_eq!(queue.deregister_writer(second), Ok(()));
+    assert_eq!(queue.is_closed(), Ok(true));
+
+    let queue = AsyncMsgQueue::new().with_poison_pill(0);
+    let writer_handle = queue.register_writer().unwrap();
+
+    assert_eq!(queue.send(writer_handle, 0), Ok(()));
+    assert_eq!(queue.read(), Err(MsgQueueError::EndOfTransmission));
+    assert_eq!(queue.deregister_writer_reporting(writer_handle), Ok(false));
+}
+
+#[test]
+pub fn num_writers_counts_registrations() {
+    let queue = AsyncMsgQueue::<i32>::new();
+
+    assert_eq!(queue.num_writers(), Ok(0));
+
+    let writers: Vec<_> = (0..3).map(|_| queue.register_writer().unwrap()).collect();
+    assert_eq!(queue.num_writers(), Ok(3));
+
+    assert_eq!(queue.deregister_writer(writers[1]), Ok(()));
+    assert_eq!(queue.num_writers(), Ok(2));
+}
+
+#[test]
+pub fn len_counts_unread_messages() {
+    let queue = AsyncMsgQueue::new();
+    let writer_handle = queue.register_writer().unwrap();
+
+    assert_eq!(queue.is_empty(), Ok(true));
+
+    for i in 0..3 { assert_eq!(queue.send(writer_handle, i), Ok(())) }
+    assert_eq!(queue.read(), Ok(0));
+
+    assert_eq!(queue.len(), Ok(2));
+    assert_eq!(queue.is_empty(), Ok(false));
+}
+
+#[test]
+pub fn bounded_queue_rejects_sends_when_full() {
+    let queue = AsyncMsgQueue::new_bounded(2);
+    let writer_handle = queue.register_writer().unwrap();
+
+    assert_eq!(queue.send(writer_handle, 1), Ok(()));
+    assert_eq!(queue.send(writer_handle, 2), Ok(()));
+    assert_eq!(queue.send(writer_handle, 3), Err(MsgQueueError::QueueFull));
+
+    assert_eq!(queue.read(), Ok(1));
+    assert_eq!(queue.send(writer_handle, 3), Ok(()));
+
+    assert_eq!(AsyncMsgQueue::<i32>::new().capacity(), None);
+}
+
+#[test]
+pub fn send_blocking_waits_for_a_read() {
+    let queue = AsyncMsgQueue::new_bounded_arc(1);
+    let writer_handle = queue.register_writer().unwrap();
+
+    assert_eq!(queue.send_blocking(writer_handle, 1), Ok(()));
+    assert_eq!(queue.send(writer_handle, 2), Err(MsgQueueError::QueueFull));
+
+    let producer = {
+        let queue = queue.clone();
+        std::thread::spawn(move || queue.send_blocking(writer_handle, 2))
+    };
+
+    std::thread::sleep(Duration::from_millis(10));
+    assert!(!producer.is_finished());
+
+    assert_eq!(queue.read(), Ok(1));
+    assert_eq!(producer.join().unwrap(), Ok(()));
+    assert_eq!(queue.read(), Ok(2));
+
+    assert_eq!(queue.send_blocking(0, 3), Err(MsgQueueError::UnknownWriter));
+}
+
+#[test]
+pub fn queue_names_round_trip() {
+    assert_eq!(AsyncMsgQueue::<i32>::new_named("orders").name(), "orders");
+    assert_eq!(AsyncMsgQueue::<i32>::new_named_arc(String::from("audit")).name(), "audit");
+    assert_eq!(AsyncMsgQueue::<i32>::new().name(), "unnamed");
+}
+
+#[test]
+pub fn dropping_shared_writer_guards_closes_queue() {
+    let queue = AsyncMsgQueue::new_arc();
+
+    {
+        let guard = queue.register_writer_guard_arc().unwrap();
+        assert_eq!(guard.send(1), Ok(()));
+
+        let guard = queue.register_writer_guard_arc().unwrap();
+        std::thread::spawn(move || guard.send(2)).join().unwrap().unwrap();
+    }
+
+    assert_eq!(queue.read(), Ok(1));
+    assert_eq!(queue.read(), Ok(2));
+    assert_eq!(queue.read(), Err(MsgQueueError::EndOfTransmission));
+}
+
+#[test]
+pub fn channel_reader_drains_after_writers_drop() {
+    let (writer, reader) = channel().unwrap();
+    let writers = vec![writer.try_clone().unwrap(), writer.try_clone().unwrap(), writer];
+
+    let handles: Vec<_> = writers.into_iter().enumerate()
+        .map(|(i, writer)| std::thread::spawn(move || writer.send(i)))
+        .collect();
+
+    for handle in handles { assert_eq!(handle.join().unwrap(), Ok(())) }
+
+    let other_reader = reader.clone();
+    let mut messages = vec![reader.read().unwrap(), other_reader.read().unwrap(), reader.read().unwrap()];
+    messages.sort();
+
+    assert_eq!(messages, vec![0, 1, 2]);
+    assert_eq!(reader.read(), Err(MsgQueueError::EndOfTransmission));
+    assert_eq!(other_reader.try_read(), Err(MsgQueueError::QueueTerminated));
+}
+
+#[test]
+pub fn iter_collects_until_end_of_transmission() {
+    let queue = AsyncMsgQueue::new_arc();
+    let writer = queue.register_writer().unwrap();
+
+    for i in 0..5 { queue.send(writer, i).unwrap() }
+    queue.deregister_writer(writer).unwrap();
+
+    let mut iter = queue.iter();
+
+    assert_eq!(iter.by_ref().collect::<Vec<_>>(), vec![0, 1, 2, 3, 4]);
+    assert_eq!(iter.error(), None);
+    assert_eq!(iter.next(), None);
+}
+
+#[test]
+pub fn drain_returns_every_available_message() {
+    let queue = AsyncMsgQueue::new();
+    let writer = queue.register_writer().unwrap();
+
+    assert_eq!(queue.drain(), Ok(vec![]));
+
+    for i in 0..5 { queue.send(writer, i).unwrap() }
+
+    assert_eq!(queue.drain(), Ok(vec![0, 1, 2, 3, 4]));
+    assert_eq!(queue.len(), Ok(0));
+
+    queue.deregister_writer(writer).unwrap();
+
+    assert_eq!(queue.drain(), Err(MsgQueueError::EndOfTransmission));
+    assert_eq!(queue.drain(), Err(MsgQueueError::QueueTerminated));
+}
+
+#[test]
+pub fn reopen_accepts_new_writers() {
+    let queue = AsyncMsgQueue::new();
+    let writer = queue.register_writer().unwrap();
+
+    queue.send(writer, 1).unwrap();
+    queue.deregister_writer(writer).unwrap();
+
+    assert_eq!(queue.is_closed(), Ok(true));
+    assert_eq!(queue.epoch(), Ok(0));
+    assert_eq!(queue.reopen(), Ok(()));
+    assert_eq!(queue.close_reason(), Ok(None));
+    assert_eq!(queue.epoch(), Ok(1));
+
+    assert_eq!(queue.reopen(), Ok(()));
+    assert_eq!(queue.epoch(), Ok(1));
+
+    let writer = queue.register_writer().unwrap();
+    assert_eq!(queue.send(writer, 2), Ok(()));
+
+    assert_eq!(queue.read_with_epoch(), Ok((0, 1)));
+    assert_eq!(queue.read_with_epoch(), Ok((1, 2)));
+
+    queue.deregister_writer(writer).unwrap();
+    assert_eq!(queue.read(), Err(MsgQueueError::EndOfTransmission));
+    assert_eq!(queue.reopen(), Err(MsgQueueError::QueueTerminated));
+}
+
+#[test]
+pub fn force_terminate_discards_messages_and_wakes_readers() {
+    let queue = AsyncMsgQueue::new_arc();
+    let writer = queue.register_writer().unwrap();
+
+    for i in 0..10 { queue.send(writer, i).unwrap() }
+
+    assert_eq!(queue.force_terminate(), Ok(()));
+    assert_eq!(queue.read(), Err(MsgQueueError::QueueTerminated));
+    assert_eq!(queue.len(), Ok(0));
+    assert_eq!(queue.send(writer, 10), Err(MsgQueueError::QueueClosed));
+
+    let queue = AsyncMsgQueue::<usize>::new_arc();
+    let _writer = queue.register_writer().unwrap();
+
+    let reader = {
+        let queue = queue.clone();
+        std::thread::spawn(move || queue.read())
+    };
+
+    std::thread::sleep(std::time::Duration::from_millis(50));
+    assert_eq!(queue.force_terminate(), Ok(()));
+    assert_eq!(reader.join().unwrap(), Err(MsgQueueError::QueueTerminated));
+}
+
+#[test]
+pub fn read_batch_waits_then_reads_up_to_max() {
+    let queue = AsyncMsgQueue::new_arc();
+    let writer = queue.register_writer().unwrap();
+
+    for i in 0..5 { queue.send(writer, i).unwrap() }
+
+    assert_eq!(queue.read_batch(3), Ok(vec![0, 1, 2]));
+    assert_eq!(queue.read_batch(3), Ok(vec![3, 4]));
+
+    let reader = {
+        let queue = queue.clone();
+        std::thread::spawn(move || queue.read_batch(3))
+    };
+
+    std::thread::sleep(std::time::Duration::from_millis(50));
+    queue.send(writer, 5).unwrap();
+    assert_eq!(reader.join().unwrap(), Ok(vec![5]));
+
+    queue.deregister_writer(writer).unwrap();
+    assert_eq!(queue.read_batch(3), Err(MsgQueueError::EndOfTransmission));
+}
+
+#[test]
+pub fn partition_closes_every_output_when_forwarding_fails() {
+    let queue = AsyncMsgQueue::<usize>::new_arc();
+    let (evens, odds, forwarder) = queue.partition(|n| n % 2 == 0).unwrap();
+    let writer_handle = queue.register_writer().unwrap();
+
+    assert_eq!(evens.force_terminate(), Ok(()));
+    assert_eq!(queue.send(writer_handle, 0), Ok(()));
+
+    assert_eq!(forwarder.join().unwrap(), Err(MsgQueueError::QueueClosed));
+    assert_eq!(odds.read(), Err(MsgQueueError::EndOfTransmission));
+}
+
+#[test]
+pub fn drop_oldest_goes_by_send_order_with_send_front() {
+    let queue = AsyncMsgQueue::<usize>::new().with_capacity_policy(2, OverflowPolicy::DropOldest);
+    let writer_handle = queue.register_writer().unwrap();
+
+    assert_eq!(queue.send(writer_handle, 0), Ok(()));
+    assert_eq!(queue.send_front(writer_handle, 9), Ok(()));
+    assert_eq!(queue.send(writer_handle, 1), Ok(()));
+
+    assert_eq!(queue.peek(), Ok(9));
+    assert_eq!(queue.len(), Ok(2));
+
+    assert_eq!(queue.send(writer_handle, 2), Ok(()));
+
+    assert_eq!(queue.dropped_count(), 2);
+    assert_eq!(queue.try_read_batch(5), Ok(vec![1, 2]));
+}
+
+#[test]
+pub fn drain_into_transforms_outside_the_lock() {
+    let queue_cell = Arc::new(std::sync::OnceLock::<std::sync::Weak<AsyncMsgQueue<usize>>>::new());
+    let transform_cell = queue_cell.clone();
+
+    let queue = Arc::new(AsyncMsgQueue::new().with_read_transform(move |n: usize| {
+        let queue = transform_cell.get().and_then(std::sync::Weak::upgrade).unwrap();
+        assert!(queue.queue.try_lock().is_ok());
+        n * 10
+    }));
+
+    queue_cell.set(Arc::downgrade(&queue)).unwrap();
+
+    let writer_handle = queue.register_writer().unwrap();
+    for n in 1..4 { assert_eq!(queue.send(writer_handle, n), Ok(())) }
+
+    let mut buf = vec![0];
+    assert_eq!(queue.drain_into(&mut buf), Ok(3));
+    assert_eq!(buf, [0, 10, 20, 30]);
+}
+
+#[test]
+pub fn sharded_registration_is_rolled_back_when_a_shard_fails() {
+    let queue = ShardedMsgQueue::<i32>::new_arc(3);
+
+    let _ = std::thread::scope(|scope| scope.spawn(|| {
+        let _writers = queue.shards[1].writers.lock().unwrap();
+        panic!("poisoning the second shard's writers")
+    }).join());
+
+    assert_eq!(queue.register_writer(), Err(NoLock));
+    assert!(queue.shards[0].writers.lock().unwrap().is_empty());
+    assert_eq!(queue.shards[0].is_closed(), Ok(false));
+}
+
+#[test]
+pub fn sharded_reader_wakes_on_send_to_any_shard() {
+    let queue = ShardedMsgQueue::new_arc(3);
+    let writer_handle = queue.register_writer().unwrap();
+
+    for n in 0..3 {
+        let reader = {
+            let queue = queue.clone();
+            std::thread::spawn(move || queue.read())
+        };
+
+        std::thread::sleep(Duration::from_millis(20));
+        assert_eq!(queue.send(writer_handle, n), Ok(()));
+        assert_eq!(reader.join().unwrap(), Ok(n));
+    }
+
+    let reader = {
+        let queue = queue.clone();
+        std::thread::spawn(move || queue.read())
+    };
+
+    std::thread::sleep(Duration::from_millis(20));
+    assert_eq!(queue.deregister_writer(writer_handle), Ok(()));
+    assert!(reader.join().unwrap().unwrap_err().is_terminal());
+}
+
+#[test]
+pub fn cancelled_read_until_passes_its_wakeup_on() {
+    let queue = AsyncMsgQueue::<usize>::new_arc();
+    let writer_handle = queue.register_writer().unwrap();
+    let cancel = Arc::new(AtomicBool::new(false));
+
+    let cancelled_reader = {
+        let (queue, cancel) = (queue.clone(), cancel.clone());
+        std::thread::spawn(move || queue.read_until(&cancel))
+    };
+
+    let reader = {
+        let queue = queue.clone();
+        std::thread::spawn(move || queue.read())
+    };
+
+    std::thread::sleep(Duration::from_millis(50));
+
+    cancel.store(true, std::sync::atomic::Ordering::Relaxed);
+    assert_eq!(queue.send(writer_handle, 1), Ok(()));
+
+    assert_eq!(reader.join().unwrap(), Ok(1));
+    assert_eq!(cancelled_reader.join().unwrap(), Err(MsgQueueError::Cancelled));
+}